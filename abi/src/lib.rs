@@ -0,0 +1,47 @@
+#![no_std]
+// 通过系统调用在内核与用户程序之间传递的数据结构，内核与用户库使用同一份定义
+// 这些类型的内存布局是系统调用 ABI 的一部分
+
+pub const MAX_SYSCALL_NUM: usize = 500;
+
+#[repr(usize)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TaskStatus {
+    UnInit,
+    Ready,
+    Running,
+    // 等待某个事件（例如睡眠到期），不在就绪队列中
+    Blocked,
+    // 已退出但尚未被父进程回收，exit_code 保存在 TCB 中
+    Zombie,
+}
+
+// sys_task_info 的参数
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TaskInfo {
+    pub status: TaskStatus,
+    // 以系统调用号为下标的调用次数
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    // 距离任务第一次被调度经过的毫秒数
+    pub time: usize,
+    // 作为实时任务错过截止时间的次数
+    pub deadline_misses: usize,
+}
+
+impl TaskInfo {
+    pub fn new() -> Self {
+        Self {
+            status: TaskStatus::UnInit,
+            syscall_times: [0; MAX_SYSCALL_NUM],
+            time: 0,
+            deadline_misses: 0,
+        }
+    }
+}
+
+impl Default for TaskInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,6 +1,15 @@
 pub const MAX_APP_NUM: usize = 20;
+// 统计系统调用次数时支持的最大系统调用号
+pub use abi::MAX_SYSCALL_NUM;
+// stride 调度中 pass 的步长基数，stride = BIG_STRIDE / priority
+#[cfg(not(any(feature = "sched_rr", feature = "sched_cfs", feature = "sched_mlfq")))]
+pub const BIG_STRIDE: u64 = 1 << 32;
 pub const USER_STACK_SIZE: usize = 4096 * 2; // 8 * . Byte;
+// 用户栈放在低 256GiB 的顶端，ELF 之后的空间留给向上增长的堆
+pub const USER_STACK_TOP: usize = 1 << 38;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+// 支持的最大 hart 数，需要与 entry.asm 中启动栈的数量一致
+pub const MAX_HARTS: usize = 4;
 pub const KERNEL_HEAP_SIZE: usize = 0x30_0000; // 3M Byte
 
 pub const APP_BASE_ADDRESS: usize = 0x80400000;
@@ -13,16 +22,13 @@ pub const CLOCK_FREQ: usize = 12500000;
 
 pub const PAGE_SIZE: usize = 12;
 
-pub const MEMORY_END: usize = 0x80800000; // 8 MiB App Memory
+// Qemu virt 平台上第一个 virtio-mmio 设备的位置，用作交换区的块设备挂在这里
+pub const VIRTIO0: usize = 0x1000_1000;
+// 需要在内核地址空间中恒等映射的 MMIO 区域：(起始地址, 长度)
+pub const MMIO: &[(usize, usize)] = &[(VIRTIO0, 0x1000)];
 
 // 跳板的位置
 pub const TRAMPOLINE: usize = usize::MAX - (1 << PAGE_SIZE) + 1;
 
 // trap_context 的情况
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - (1 << PAGE_SIZE);
-
-pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
-    let top = TRAMPOLINE - app_id * (KERNEL_STACK_SIZE + 1 << PAGE_SIZE);
-    let bottom = top - KERNEL_STACK_SIZE;
-    (bottom, top)
-}
@@ -1,4 +1,5 @@
 use crate::sbi::console_putchar;
+use crate::sync::SpinLock;
 use core::fmt::{self, Write};
 
 struct Stdout;
@@ -12,8 +13,11 @@ impl fmt::Write for Stdout {
     }
 }
 
+// 多个 hart 同时输出时，保证每次 print 的内容不被打断
+static STDOUT: SpinLock<Stdout> = SpinLock::new(Stdout);
+
 pub fn print(args: fmt::Arguments) {
-    Stdout.write_fmt(args).unwrap();
+    STDOUT.lock().write_fmt(args).unwrap();
 }
 
 //TODO: 着色输出
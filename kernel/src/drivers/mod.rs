@@ -0,0 +1 @@
+pub mod virtio_blk;
@@ -0,0 +1,264 @@
+use core::mem::size_of;
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+use crate::mm::{frame_alloc_contiguous, FrameRangeTracker};
+
+// virtio-mmio 寄存器的偏移，同时支持 legacy（version 1）与 modern（version 2）两种接口
+const MAGIC_VALUE: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const DEVICE_FEATURES_SEL: usize = 0x014;
+const DRIVER_FEATURES: usize = 0x020;
+const DRIVER_FEATURES_SEL: usize = 0x024;
+const GUEST_PAGE_SIZE: usize = 0x028;
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c;
+const QUEUE_PFN: usize = 0x040;
+const QUEUE_READY: usize = 0x044;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+const QUEUE_DESC_LOW: usize = 0x080;
+const QUEUE_DESC_HIGH: usize = 0x084;
+const QUEUE_DRIVER_LOW: usize = 0x090;
+const QUEUE_DRIVER_HIGH: usize = 0x094;
+const QUEUE_DEVICE_LOW: usize = 0x0a0;
+const QUEUE_DEVICE_HIGH: usize = 0x0a4;
+// 块设备的配置空间，开头是以扇区为单位的容量
+const CONFIG_CAPACITY: usize = 0x100;
+
+const MAGIC: u32 = 0x7472_6976;
+const DEVICE_ID_BLOCK: u32 = 2;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+// modern 接口必须协商的 VIRTIO_F_VERSION_1，位于第二组特性的第 0 位
+const FEATURE_VERSION_1: u32 = 1;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+const BLK_T_IN: u32 = 0;
+const BLK_T_OUT: u32 = 1;
+const BLK_S_OK: u8 = 0;
+
+pub const SECTOR_SIZE: usize = 512;
+// 同一时间只有一个请求，每个请求占用 3 个描述符：请求头、数据、状态
+const QUEUE_SIZE: usize = 4;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VirtqDesc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct VirtqAvail {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VirtqUsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+struct VirtqUsed {
+    flags: u16,
+    idx: u16,
+    ring: [VirtqUsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+#[repr(C)]
+struct BlkReqHeader {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+// legacy 接口要求描述符表与 avail 环连续存放，used 环从下一个页开始
+#[repr(C, align(4096))]
+struct DriverArea {
+    desc: [VirtqDesc; QUEUE_SIZE],
+    avail: VirtqAvail,
+}
+
+#[repr(C, align(4096))]
+struct DeviceArea {
+    used: VirtqUsed,
+    header: BlkReqHeader,
+    status: u8,
+}
+
+#[repr(C)]
+struct VirtQueue {
+    driver: DriverArea,
+    device: DeviceArea,
+}
+
+// 以轮询方式工作的 virtio 块设备驱动，只使用一个队列，每次只提交一个请求
+pub struct VirtIOBlk {
+    base: usize,
+    capacity: usize,
+    last_used: u16,
+    // 队列所在的物理地址连续的页帧，设备通过物理地址访问，内核通过恒等映射访问
+    queue_frames: FrameRangeTracker,
+}
+
+impl VirtIOBlk {
+    // 探测并初始化 base 处的 virtio 块设备，该位置没有块设备时返回 None
+    pub fn new(base: usize) -> Option<Self> {
+        let mut blk = Self {
+            base,
+            capacity: 0,
+            last_used: 0,
+            queue_frames: frame_alloc_contiguous(size_of::<VirtQueue>() >> 12, 1)?,
+        };
+        if blk.read_reg(MAGIC_VALUE) != MAGIC || blk.read_reg(DEVICE_ID) != DEVICE_ID_BLOCK {
+            return None;
+        }
+        let version = blk.read_reg(VERSION);
+        blk.write_reg(STATUS, 0);
+        blk.write_reg(STATUS, STATUS_ACKNOWLEDGE);
+        blk.write_reg(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        // 不使用任何可选特性
+        blk.write_reg(DEVICE_FEATURES_SEL, 0);
+        blk.write_reg(DRIVER_FEATURES_SEL, 0);
+        blk.write_reg(DRIVER_FEATURES, 0);
+        let mut status = STATUS_ACKNOWLEDGE | STATUS_DRIVER;
+        if version == 1 {
+            blk.write_reg(GUEST_PAGE_SIZE, 4096);
+        } else {
+            blk.write_reg(DRIVER_FEATURES_SEL, 1);
+            blk.write_reg(DRIVER_FEATURES, FEATURE_VERSION_1);
+            status |= STATUS_FEATURES_OK;
+            blk.write_reg(STATUS, status);
+            if blk.read_reg(STATUS) & STATUS_FEATURES_OK == 0 {
+                return None;
+            }
+        }
+        blk.write_reg(QUEUE_SEL, 0);
+        if (blk.read_reg(QUEUE_NUM_MAX) as usize) < QUEUE_SIZE {
+            return None;
+        }
+        blk.write_reg(QUEUE_NUM, QUEUE_SIZE as u32);
+        let queue = blk.queue();
+        let desc = addr_of!(queue.driver.desc) as usize;
+        let driver = addr_of!(queue.driver.avail) as usize;
+        let device = addr_of!(queue.device.used) as usize;
+        if version == 1 {
+            blk.write_reg(QUEUE_ALIGN, 4096);
+            blk.write_reg(QUEUE_PFN, (desc >> 12) as u32);
+        } else {
+            blk.write_reg(QUEUE_DESC_LOW, desc as u32);
+            blk.write_reg(QUEUE_DESC_HIGH, (desc >> 32) as u32);
+            blk.write_reg(QUEUE_DRIVER_LOW, driver as u32);
+            blk.write_reg(QUEUE_DRIVER_HIGH, (driver >> 32) as u32);
+            blk.write_reg(QUEUE_DEVICE_LOW, device as u32);
+            blk.write_reg(QUEUE_DEVICE_HIGH, (device >> 32) as u32);
+            blk.write_reg(QUEUE_READY, 1);
+        }
+        blk.write_reg(STATUS, status | STATUS_DRIVER_OK);
+        let capacity = unsafe { read_volatile((base + CONFIG_CAPACITY) as *const u64) };
+        blk.capacity = capacity as usize;
+        Some(blk)
+    }
+
+    // 设备的扇区数
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // 从 sector 开始读取 buf.len() 字节，buf 需要位于恒等映射的内存中
+    pub fn read(&mut self, sector: usize, buf: &mut [u8]) {
+        self.request(BLK_T_IN, sector, buf.as_mut_ptr() as usize, buf.len());
+    }
+
+    // 从 sector 开始写入 buf.len() 字节，buf 需要位于恒等映射的内存中
+    pub fn write(&mut self, sector: usize, buf: &[u8]) {
+        self.request(BLK_T_OUT, sector, buf.as_ptr() as usize, buf.len());
+    }
+
+    fn request(&mut self, req_type: u32, sector: usize, addr: usize, len: usize) {
+        assert!(len % SECTOR_SIZE == 0 && sector + len / SECTOR_SIZE <= self.capacity);
+        unsafe {
+            let queue = self.queue();
+            queue.device.header = BlkReqHeader {
+                req_type,
+                reserved: 0,
+                sector: sector as u64,
+            };
+            queue.device.status = 0xff;
+            let data_flags = if req_type == BLK_T_IN {
+                DESC_F_NEXT | DESC_F_WRITE
+            } else {
+                DESC_F_NEXT
+            };
+            queue.driver.desc[0] = VirtqDesc {
+                addr: addr_of!(queue.device.header) as u64,
+                len: core::mem::size_of::<BlkReqHeader>() as u32,
+                flags: DESC_F_NEXT,
+                next: 1,
+            };
+            queue.driver.desc[1] = VirtqDesc {
+                addr: addr as u64,
+                len: len as u32,
+                flags: data_flags,
+                next: 2,
+            };
+            queue.driver.desc[2] = VirtqDesc {
+                addr: addr_of!(queue.device.status) as u64,
+                len: 1,
+                flags: DESC_F_WRITE,
+                next: 0,
+            };
+            let avail_idx = read_volatile(addr_of!(queue.driver.avail.idx));
+            queue.driver.avail.ring[avail_idx as usize % QUEUE_SIZE] = 0;
+            // 描述符写完之后才能让设备看到新的 avail 下标
+            fence(Ordering::SeqCst);
+            write_volatile(
+                addr_of_mut!(queue.driver.avail.idx),
+                avail_idx.wrapping_add(1),
+            );
+            fence(Ordering::SeqCst);
+            self.write_reg(QUEUE_NOTIFY, 0);
+            while read_volatile(addr_of!(self.queue().device.used.idx)) == self.last_used {
+                core::hint::spin_loop();
+            }
+            fence(Ordering::SeqCst);
+            self.last_used = self.last_used.wrapping_add(1);
+            let interrupt_status = self.read_reg(INTERRUPT_STATUS);
+            self.write_reg(INTERRUPT_ACK, interrupt_status);
+            let status = read_volatile(addr_of!(self.queue().device.status));
+            assert_eq!(status, BLK_S_OK, "[kernel] virtio-blk request failed");
+        }
+    }
+
+    // 队列的内容只通过 &mut self 访问，借用期间不能再发起其他请求
+    fn queue(&mut self) -> &mut VirtQueue {
+        self.queue_frames.ppn.get_mut()
+    }
+
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+}
@@ -1,12 +1,38 @@
     .section .text.entry
     .globl _start
 _start:
-    la sp, boot_stack_top
+    # a0 = hartid，a1 = 设备树（DTB）的物理地址，启动 hart 从这里进入
+    # set_boot_stack 不会修改 a1，直接传给 rust_main
+    call set_boot_stack
     call rust_main
 
+    # 其余的 hart 由启动 hart 通过 SBI HSM 扩展从这里启动
+    .globl _start_secondary
+_start_secondary:
+    call set_boot_stack
+    call rust_main_secondary
+
+    # tp = hartid，sp = boot_stack_top - hartid * 64KiB
+    # hartid 不小于 MAX_HARTS（与 config.rs 中一致）的 hart 没有启动栈，
+    # 也不能作为各个 per-hart 数组的下标，直接停住
+set_boot_stack:
+    li t0, 4
+    bgeu a0, t0, park
+    mv tp, a0
+    la sp, boot_stack_top
+    li t0, 4096 * 16
+    mul t0, t0, a0
+    sub sp, sp, t0
+    ret
+
+park:
+    wfi
+    j park
+
     .section .bss.stack
     .globl boot_stack
 boot_stack:
-    .space 4096 * 16
+    # 每个 hart 64KiB，共 MAX_HARTS 个
+    .space 4096 * 16 * 4
     .globl boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
@@ -0,0 +1,138 @@
+// 扁平设备树（FDT）的最小解析，只用于启动时获取物理内存的范围以及其中保留的部分
+// 格式见 Devicetree Specification 第 5 章，其中的数据都是大端序
+use alloc::vec::Vec;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+fn read_u32(addr: usize) -> u32 {
+    u32::from_be(unsafe { (addr as *const u32).read() })
+}
+
+// 由 cells 个 32 位数组成的数
+fn read_cells(addr: usize, cells: usize) -> usize {
+    (0..cells).fold(0, |value, i| value << 32 | read_u32(addr + 4 * i) as usize)
+}
+
+fn read_str(addr: usize) -> &'static str {
+    let mut len = 0;
+    while unsafe { *((addr + len) as *const u8) } != 0 {
+        len += 1;
+    }
+    let bytes = unsafe { core::slice::from_raw_parts(addr as *const u8, len) };
+    core::str::from_utf8(bytes).unwrap_or("")
+}
+
+fn align4(addr: usize) -> usize {
+    (addr + 3) & !3
+}
+
+// reg 属性中的每一项：(起始地址, 大小)
+fn read_reg(
+    value: usize,
+    len: usize,
+    address_cells: usize,
+    size_cells: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    let entry_size = (address_cells + size_cells) * 4;
+    let end = value + len / entry_size * entry_size;
+    (value..end).step_by(entry_size).map(move |entry| {
+        (
+            read_cells(entry, address_cells),
+            read_cells(entry + address_cells * 4, size_cells),
+        )
+    })
+}
+
+// 设备树中与物理内存有关的信息，每个区域为 (起始地址, 大小)
+pub struct MemoryInfo {
+    // 所有 /memory 节点的 reg 描述的内存区域
+    pub regions: Vec<(usize, usize)>,
+    // 内存中不能使用的部分：设备树本身、内存保留块中的条目以及 /reserved-memory 的子节点
+    pub reserved: Vec<(usize, usize)>,
+}
+
+pub fn memory_info(dtb: usize) -> MemoryInfo {
+    assert_eq!(
+        read_u32(dtb),
+        FDT_MAGIC,
+        "[kernel] invalid device tree at {:#x}",
+        dtb
+    );
+    let mut reserved = alloc::vec![(dtb, read_u32(dtb + 4) as usize)];
+    // 内存保留块由 64 位的 (地址, 大小) 组成，以两者均为 0 的一项结束
+    let mut entry = dtb + read_u32(dtb + 16) as usize;
+    loop {
+        let (address, size) = (read_cells(entry, 2), read_cells(entry + 8, 2));
+        if address == 0 && size == 0 {
+            break;
+        }
+        reserved.push((address, size));
+        entry += 16;
+    }
+    let struct_base = dtb + read_u32(dtb + 8) as usize;
+    let strings_base = dtb + read_u32(dtb + 12) as usize;
+    // reg 中地址与大小的格式由父节点的 #address-cells 与 #size-cells 决定，缺省值见规范
+    let mut address_cells = 2;
+    let mut size_cells = 1;
+    let mut reserved_address_cells = 2;
+    let mut reserved_size_cells = 1;
+    let mut regions = Vec::new();
+    // 根节点位于第 1 层，内存节点与 /reserved-memory 是根节点的子节点，保留的区域在第 3 层
+    let mut depth = 0;
+    let mut in_memory = false;
+    let mut in_reserved = false;
+    let mut pos = struct_base;
+    loop {
+        let token = read_u32(pos);
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = read_str(pos);
+                pos = align4(pos + name.len() + 1);
+                depth += 1;
+                in_memory = depth == 2 && (name == "memory" || name.starts_with("memory@"));
+                if depth == 2 {
+                    in_reserved = name == "reserved-memory";
+                }
+            }
+            FDT_END_NODE => {
+                depth -= 1;
+                in_memory = false;
+                if depth < 2 {
+                    in_reserved = false;
+                }
+            }
+            FDT_PROP => {
+                let len = read_u32(pos) as usize;
+                let name = read_str(strings_base + read_u32(pos + 4) as usize);
+                let value = pos + 8;
+                pos = align4(value + len);
+                if depth == 1 && name == "#address-cells" {
+                    address_cells = read_u32(value) as usize;
+                    reserved_address_cells = address_cells;
+                } else if depth == 1 && name == "#size-cells" {
+                    size_cells = read_u32(value) as usize;
+                    reserved_size_cells = size_cells;
+                } else if in_memory && name == "reg" {
+                    regions.extend(read_reg(value, len, address_cells, size_cells));
+                } else if in_reserved && depth == 2 && name == "#address-cells" {
+                    reserved_address_cells = read_u32(value) as usize;
+                } else if in_reserved && depth == 2 && name == "#size-cells" {
+                    reserved_size_cells = read_u32(value) as usize;
+                } else if in_reserved && depth == 3 && name == "reg" {
+                    let cells = (reserved_address_cells, reserved_size_cells);
+                    reserved.extend(read_reg(value, len, cells.0, cells.1));
+                }
+            }
+            FDT_NOP => {}
+            FDT_END => break,
+            _ => panic!("[kernel] invalid device tree token {:#x}", token),
+        }
+    }
+    MemoryInfo { regions, reserved }
+}
@@ -2,6 +2,7 @@ use crate::config;
 use crate::config::KERNEL_STACK_SIZE;
 use crate::config::USER_STACK_SIZE;
 use crate::trap::context;
+use alloc::vec::Vec;
 use core::arch::asm;
 
 
@@ -98,3 +99,35 @@ pub fn get_app_data(app_id: usize) -> &'static [u8] {
         )
     }
 }
+
+lazy_static::lazy_static! {
+    // 从 link_app.S 中的 _app_names 解析出所有应用的名字
+    static ref APP_NAMES: Vec<&'static str> = {
+        let num_app = get_num_app();
+        extern "C" {
+            fn _app_names();
+        }
+        let mut start = _app_names as usize as *const u8;
+        let mut v = Vec::new();
+        unsafe {
+            for _ in 0..num_app {
+                let mut end = start;
+                while end.read_volatile() != b'\0' {
+                    end = end.add(1);
+                }
+                let slice = core::slice::from_raw_parts(start, end as usize - start as usize);
+                let str = core::str::from_utf8(slice).unwrap();
+                v.push(str);
+                // 跳过 \0
+                start = end.add(1);
+            }
+        }
+        v
+    };
+}
+
+pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
+    (0..get_num_app())
+        .find(|&i| APP_NAMES[i] == name)
+        .map(get_app_data)
+}
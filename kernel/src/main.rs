@@ -6,9 +6,12 @@
 #[macro_use]
 mod console;
 mod config;
+mod drivers;
+mod fdt;
 mod lang_item;
 mod loader;
 mod sbi;
+mod smp;
 
 mod mm;
 mod sync;
@@ -25,17 +28,32 @@ global_asm!(include_str!("entry.asm"));
 global_asm!(include_str!("link_app.S"));
 
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(hart_id: usize, dtb: usize) -> ! {
     clear_bss();
-    println!("[Kernel] Hello, world!");
-    mm::init();
+    println!("[Kernel] Hello, world! boot hart = {}", hart_id);
+    mm::init(dtb);
     println!("[kernel] mm init success!!");
     trap::init();
     // loader::load_apps();
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
-    task::start_run_first_task();
-    panic!("Shutdown  machine");
+    task::add_apps();
+    #[cfg(feature = "kernel_test")]
+    task::kernel_thread_test();
+    // 任务都加入就绪队列之后再启动其余的 hart，避免它们看到没有任务而直接关机
+    smp::start_secondary_harts();
+    task::run_tasks();
+}
+
+// 其余 hart 的入口，内核的全局数据已经由启动 hart 初始化完成
+#[no_mangle]
+pub fn rust_main_secondary(hart_id: usize) -> ! {
+    mm::init_secondary();
+    trap::init();
+    trap::enable_timer_interrupt();
+    timer::set_next_trigger();
+    println!("[kernel] hart {} started", hart_id);
+    task::run_tasks();
 }
 
 fn clear_bss() {
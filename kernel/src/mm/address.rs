@@ -81,7 +81,10 @@ impl PhysAddr {
         PhysPageNum(self.0 / (1 << PAGE_SIZE))
     }
     pub fn ceil(&self) -> PhysPageNum {
-        PhysPageNum((self.0 + (1 << (PAGE_SIZE - 1))) / (1 << PAGE_SIZE))
+        PhysPageNum((self.0 + (1 << PAGE_SIZE) - 1) / (1 << PAGE_SIZE))
+    }
+    pub fn get_mut<T>(&self) -> &'static mut T {
+        unsafe { (self.0 as *mut T).as_mut().unwrap() }
     }
 }
 
@@ -94,7 +97,7 @@ impl VirtAddr {
         VirtPageNum(self.0 / (1 << PAGE_SIZE))
     }
     pub fn ceil(&self) -> VirtPageNum {
-        VirtPageNum((self.0 + (1 << (PAGE_SIZE - 1))) / (1 << PAGE_SIZE))
+        VirtPageNum((self.0 + (1 << PAGE_SIZE) - 1) / (1 << PAGE_SIZE))
     }
 }
 
@@ -1,8 +1,8 @@
 use core::fmt::{self, Debug, Formatter};
 
-use alloc::vec::Vec;
+use alloc::{collections::BTreeMap, vec::Vec};
 
-use crate::{config::MEMORY_END, mm::address::PhysAddr, sync::UPSafeCell};
+use crate::{mm::address::PhysAddr, sync::SpinLock};
 
 use super::address::PhysPageNum;
 
@@ -10,71 +10,222 @@ trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    // 分配 count 个连续的页帧，起始页号按 align 个页帧对齐，align 需要是 2 的幂
+    fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum>;
+    // 回收 alloc_contiguous 分配的页帧，count 需要与分配时相同
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, count: usize);
+    // 剩余可以分配的页帧数
+    fn free_count(&self) -> usize;
 }
 
-// 使用 内核堆 生成了供应用使用的 栈式物理页帧
-pub struct StackFrameAllocator {
-    current: usize,
-    end: usize,
-    recycled: Vec<usize>,
+// 空闲块的阶数上限，第 k 阶的块包含 2^k 个页帧，最大的块为 1GiB，与最大的页相同
+const MAX_ORDER: usize = 19;
+// 空闲链表的结束标记
+const NIL: usize = usize::MAX;
+
+// 空闲块的第一个页帧中保存的链表节点，把同一阶的空闲块串成双向链表
+// 页帧都在内核的恒等映射中，空闲时内容没有用处，分配时由 FrameTracker 清零
+struct FreeNode {
+    prev: usize,
+    next: usize,
+}
+
+fn free_node(ppn: usize) -> &'static mut FreeNode {
+    PhysPageNum(ppn).get_mut()
+}
+
+// 伙伴系统：空闲的页帧以 2 的幂大小、按自身大小对齐的块管理
+// 分配时拆分更大的块，回收时与空闲的伙伴块合并，每次操作最多访问 MAX_ORDER 个空闲链表，
+// 链表的插入与删除以及判断伙伴块是否空闲都是 O(1) 的
+pub struct BuddyFrameAllocator {
+    // 第 k 阶空闲链表中第一个块的起始页号
+    heads: [usize; MAX_ORDER],
+    // 第 k 个位图的第 i 位表示从 base 开始的第 i 个 k 阶块是否空闲，回收时用来查找伙伴块
+    // base 按最大的块对齐，块与它的伙伴总在 base 之后
+    free_bits: Vec<Vec<u64>>,
+    base: usize,
+    free: usize,
+    // 只在 debug 构建中检查重复回收：每个页帧一位，为 1 表示已经分配出去
+    #[cfg(debug_assertions)]
+    start: usize,
+    #[cfg(debug_assertions)]
+    allocated: Vec<u64>,
 }
 
-impl FrameAllocator for StackFrameAllocator {
+impl FrameAllocator for BuddyFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
-            end: 0,
-            recycled: Vec::new(),
+            heads: [NIL; MAX_ORDER],
+            free_bits: Vec::new(),
+            base: 0,
+            free: 0,
+            #[cfg(debug_assertions)]
+            start: 0,
+            #[cfg(debug_assertions)]
+            allocated: Vec::new(),
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        // println!("current = {:#}, end = {:#}", self.current, self.end);
-        // 优先尝试从回收队列中取出一个页帧
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
-        } else if self.current == self.end {
-            // 回收队列没有页帧，则从当前页帧开始，到结束页帧
-            // 没有页帧可用，直接返回GG
-            println!("No available frame");
-            None
-        } else {
-            // 有页帧可用，则返回该页帧(current的值)
-            self.current += 1;
-            Some((self.current - 1).into())
-        }
+        self.alloc_contiguous(1, 1)
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
-        // physical page number
-        let ppn = ppn.0;
-        // 寻找的 ppn 还未分配过或者已经回收了
-        if ppn >= self.current || self.recycled.iter().find(|&v| *v == ppn).is_some() {
-            panic!("dealloc invalid frame ppn={:#x}", ppn);
-        }
-        // 在 recycled 中插入 ppn，表示其被回收
-        self.recycled.push(ppn);
+        self.dealloc_contiguous(ppn, 1);
+    }
+    fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum> {
+        assert!(count > 0 && align.is_power_of_two());
+        let order = (count.next_power_of_two().trailing_zeros() as usize)
+            .max(align.trailing_zeros() as usize);
+        let ppn = self.alloc_block(order)?;
+        // 块中超出 count 的部分立即放回
+        self.free_range(ppn + count, ppn + (1 << order));
+        self.free -= count;
+        #[cfg(debug_assertions)]
+        self.mark_allocated(ppn, count, true);
+        Some(ppn.into())
+    }
+    fn dealloc_contiguous(&mut self, ppn: PhysPageNum, count: usize) {
+        #[cfg(debug_assertions)]
+        self.mark_allocated(ppn.0, count, false);
+        self.free_range(ppn.0, ppn.0 + count);
+        self.free += count;
+    }
+    fn free_count(&self) -> usize {
+        self.free
     }
 }
 
-impl StackFrameAllocator {
-    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
-        self.end = r.0;
+impl BuddyFrameAllocator {
+    // ranges 为按起始位置排序、互不重叠的若干段 [l, r)
+    pub fn init(&mut self, ranges: &[(PhysPageNum, PhysPageNum)]) {
+        let (l, r) = match (ranges.first(), ranges.last()) {
+            (Some(&(l, _)), Some(&(_, r))) => (l.0, r.0),
+            _ => return,
+        };
+        self.base = l & !((1 << (MAX_ORDER - 1)) - 1);
+        self.free_bits = (0..MAX_ORDER)
+            .map(|k| alloc::vec![0; ((r - self.base) >> k) / 64 + 1])
+            .collect();
+        #[cfg(debug_assertions)]
+        {
+            self.start = l;
+            self.allocated = alloc::vec![0; (r - l + 63) / 64];
+        }
+        for &(l, r) in ranges {
+            self.free_range(l.0, r.0);
+            self.free += r.0 - l.0;
+        }
+    }
+    // 取出一个 order 阶的块，没有时拆分更高阶的块，拆出的后一半放回低一阶的链表
+    fn alloc_block(&mut self, order: usize) -> Option<usize> {
+        let found = (order..MAX_ORDER).find(|&k| self.heads[k] != NIL)?;
+        let ppn = self.heads[found];
+        self.remove(ppn, found);
+        for k in (order..found).rev() {
+            self.push(ppn + (1 << k), k);
+        }
+        Some(ppn)
+    }
+    // 放回一个 order 阶的块，伙伴块也空闲时合并成高一阶的块，直到不能合并为止
+    fn dealloc_block(&mut self, mut ppn: usize, mut order: usize) {
+        while order + 1 < MAX_ORDER && self.is_free(ppn ^ (1 << order), order) {
+            self.remove(ppn ^ (1 << order), order);
+            ppn &= !(1 << order);
+            order += 1;
+        }
+        self.push(ppn, order);
+    }
+    // 将 [start, end) 拆成尽量大的对齐的块放回
+    fn free_range(&mut self, mut start: usize, end: usize) {
+        while start < end {
+            let order = (0..MAX_ORDER)
+                .rev()
+                .find(|&k| start & ((1 << k) - 1) == 0 && start + (1 << k) <= end)
+                .unwrap();
+            self.dealloc_block(start, order);
+            start += 1 << order;
+        }
+    }
+    // ppn 处的 order 阶块在位图中的位置
+    fn bit(&self, ppn: usize, order: usize) -> (usize, u64) {
+        let i = (ppn - self.base) >> order;
+        (i / 64, 1 << (i % 64))
+    }
+    // 超出位图范围的块不是可以分配的内存，视为不空闲
+    fn is_free(&self, ppn: usize, order: usize) -> bool {
+        let (idx, bit) = self.bit(ppn, order);
+        self.free_bits[order]
+            .get(idx)
+            .map_or(false, |word| word & bit != 0)
+    }
+    // 将空闲块插入 order 阶链表的头部
+    fn push(&mut self, ppn: usize, order: usize) {
+        let next = self.heads[order];
+        *free_node(ppn) = FreeNode { prev: NIL, next };
+        if next != NIL {
+            free_node(next).prev = ppn;
+        }
+        self.heads[order] = ppn;
+        let (idx, bit) = self.bit(ppn, order);
+        self.free_bits[order][idx] |= bit;
+    }
+    // 将空闲块从 order 阶链表中删除
+    fn remove(&mut self, ppn: usize, order: usize) {
+        let FreeNode { prev, next } = *free_node(ppn);
+        if prev == NIL {
+            self.heads[order] = next;
+        } else {
+            free_node(prev).next = next;
+        }
+        if next != NIL {
+            free_node(next).prev = prev;
+        }
+        let (idx, bit) = self.bit(ppn, order);
+        self.free_bits[order][idx] &= !bit;
+    }
+    #[cfg(debug_assertions)]
+    fn mark_allocated(&mut self, ppn: usize, count: usize, allocated: bool) {
+        for ppn in ppn..ppn + count {
+            let (idx, bit) = ((ppn - self.start) / 64, 1u64 << ((ppn - self.start) % 64));
+            if allocated {
+                self.allocated[idx] |= bit;
+            } else {
+                // 寻找的 ppn 还未分配过或者已经回收了
+                if self.allocated[idx] & bit == 0 {
+                    panic!("dealloc invalid frame ppn={:#x}", ppn);
+                }
+                self.allocated[idx] &= !bit;
+            }
+        }
     }
 }
 
 lazy_static::lazy_static! {
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<StackFrameAllocator> =
-        unsafe {UPSafeCell::new(StackFrameAllocator::new())};
+    pub static ref FRAME_ALLOCATOR: SpinLock<BuddyFrameAllocator> =
+        SpinLock::new(BuddyFrameAllocator::new());
+    // 被多个 FrameTracker 共享的页帧的引用计数，不在其中的页帧只有一个引用
+    static ref FRAME_REF_COUNTS: SpinLock<BTreeMap<usize, usize>> = SpinLock::new(BTreeMap::new());
 }
+
+// 同一个页帧可以被多个 FrameTracker 共享（例如写时复制），最后一个被释放时才回收页帧
 pub struct FrameTracker {
     pub ppn: PhysPageNum,
 }
 
 impl FrameTracker {
+    // 新分配的页帧清零，页表和按需分配的页都依赖这一点
     pub fn new(ppn: PhysPageNum) -> Self {
+        ppn.get_page_array().fill(0);
         Self { ppn }
     }
 }
+impl Clone for FrameTracker {
+    // 共享同一个页帧，引用计数加一
+    fn clone(&self) -> Self {
+        *FRAME_REF_COUNTS.lock().entry(self.ppn.0).or_insert(1) += 1;
+        Self { ppn: self.ppn }
+    }
+}
+
 impl Debug for FrameTracker {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!("FrameTracker:PPN={:#x}", self.ppn.0))
@@ -83,29 +234,135 @@ impl Debug for FrameTracker {
 
 impl Drop for FrameTracker {
     fn drop(&mut self) {
-        // 在 Tracker 结束的时候就回收其 Tack 的页帧
+        // 在 Tracker 结束的时候就回收其 Tack 的页帧，页帧仍被共享时只减少引用计数
+        let mut ref_counts = FRAME_REF_COUNTS.lock();
+        if let Some(count) = ref_counts.get_mut(&self.ppn.0) {
+            *count -= 1;
+            if *count == 1 {
+                ref_counts.remove(&self.ppn.0);
+            }
+            return;
+        }
+        drop(ref_counts);
         frame_dealloc(self.ppn);
     }
 }
 
-pub fn init_frame_allocator() {
-    extern "C" {
-        fn ekernel();
+// 一段连续的页帧，用于 DMA 缓冲区等需要物理地址连续的场合，被释放时一起回收
+pub struct FrameRangeTracker {
+    pub ppn: PhysPageNum,
+    pub count: usize,
+}
+
+impl FrameRangeTracker {
+    pub fn new(ppn: PhysPageNum, count: usize) -> Self {
+        for i in 0..count {
+            PhysPageNum(ppn.0 + i).get_page_array().fill(0);
+        }
+        Self { ppn, count }
+    }
+}
+
+impl Debug for FrameRangeTracker {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "FrameRangeTracker:PPN={:#x},count={}",
+            self.ppn.0, self.count
+        ))
+    }
+}
+
+impl Drop for FrameRangeTracker {
+    fn drop(&mut self) {
+        FRAME_ALLOCATOR
+            .lock()
+            .dealloc_contiguous(self.ppn, self.count);
     }
-    FRAME_ALLOCATOR.exclusive_access().init(
-        PhysAddr::from(ekernel as usize).ceil().into(),
-        PhysAddr::from(MEMORY_END).floor().into(),
-    )
+}
+
+pub fn init_frame_allocator() {
+    let ranges: Vec<(PhysPageNum, PhysPageNum)> = super::memory_regions()
+        .into_iter()
+        .map(|(start, end)| (PhysAddr::from(start).floor(), PhysAddr::from(end).floor()))
+        .collect();
+    FRAME_ALLOCATOR.lock().init(&ranges);
 }
 
 pub fn frame_alloc() -> Option<FrameTracker> {
     // 这里我们希望使用另一个对象包裹 PhysPageNum, 通过这样的方式利用该对象的生命周期（Drop）来回收页帧
     FRAME_ALLOCATOR
-        .exclusive_access()
+        .lock()
         .alloc()
         .map(FrameTracker::new)
 }
 
+// 分配 count 个连续的页帧，起始页号按 align 个页帧对齐
+pub fn frame_alloc_contiguous(count: usize, align: usize) -> Option<FrameRangeTracker> {
+    FRAME_ALLOCATOR
+        .lock()
+        .alloc_contiguous(count, align)
+        .map(|ppn| FrameRangeTracker::new(ppn, count))
+}
+
+pub fn frame_free_count() -> usize {
+    FRAME_ALLOCATOR.lock().free_count()
+}
+
+// 页帧被多少个 FrameTracker 共享
+pub fn frame_ref_count(ppn: PhysPageNum) -> usize {
+    FRAME_REF_COUNTS.lock().get(&ppn.0).copied().unwrap_or(1)
+}
+
 pub fn frame_dealloc(ppn: PhysPageNum) {
-    FRAME_ALLOCATOR.exclusive_access().dealloc(ppn)
+    FRAME_ALLOCATOR.lock().dealloc(ppn)
+}
+
+#[cfg(feature = "kernel_test")]
+pub fn frame_allocator_test() {
+    // 在从全局分配器取出的一段页帧上单独建立一个分配器，检查拆分、对齐与合并
+    const COUNT: usize = 64;
+    let range = frame_alloc_contiguous(COUNT, COUNT).unwrap();
+    let start = range.ppn.0;
+    let mut allocator = BuddyFrameAllocator::new();
+    allocator.init(&[(PhysPageNum(start), PhysPageNum(start + COUNT))]);
+    assert_eq!(allocator.free_count(), COUNT);
+    // 逐个分配完所有页帧，每个页帧恰好分配一次
+    let mut frames: Vec<usize> = (0..COUNT).map(|_| allocator.alloc().unwrap().0).collect();
+    assert!(allocator.alloc().is_none());
+    assert_eq!(allocator.free_count(), 0);
+    frames.sort_unstable();
+    assert!(frames.iter().enumerate().all(|(i, &ppn)| ppn == start + i));
+    // 交错地回收，全部回收之后重新合并成一整块
+    let interleaved = frames
+        .iter()
+        .step_by(2)
+        .chain(frames.iter().skip(1).step_by(2));
+    for (i, &ppn) in interleaved.enumerate() {
+        allocator.dealloc(PhysPageNum(ppn));
+        assert_eq!(allocator.free_count(), i + 1);
+    }
+    let whole = allocator.alloc_contiguous(COUNT, COUNT).unwrap();
+    assert_eq!(whole.0, start);
+    allocator.dealloc_contiguous(whole, COUNT);
+    // 3 个页帧按 4 对齐：占用一个 2 阶块，多出的一个页帧立即放回
+    let single = allocator.alloc().unwrap();
+    let three = allocator.alloc_contiguous(3, 4).unwrap();
+    assert_eq!(three.0 % 4, 0);
+    assert!(single.0 < three.0 || single.0 >= three.0 + 3);
+    assert_eq!(allocator.free_count(), COUNT - 4);
+    allocator.dealloc_contiguous(three, 3);
+    allocator.dealloc(single);
+    assert_eq!(allocator.free_count(), COUNT);
+    assert_eq!(
+        allocator.alloc_contiguous(COUNT, COUNT).map(|ppn| ppn.0),
+        Some(start)
+    );
+    drop(range);
+    // 全局分配器的空闲页帧数随分配与回收变化
+    let before = frame_free_count();
+    let frame = frame_alloc().unwrap();
+    assert_eq!(frame_free_count(), before - 1);
+    drop(frame);
+    assert_eq!(frame_free_count(), before);
+    println!("Frame allocator test passed");
 }
@@ -1,17 +1,38 @@
-use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
 
-use crate::config::{KERNEL_HEAP_SIZE, KERNEL_STACK_SIZE};
+use buddy_system_allocator::Heap;
+
+use crate::config::KERNEL_HEAP_SIZE;
+use crate::sync::SpinLock;
+
+// buddy_system_allocator 自带的 LockedHeap 持有锁时不关中断，
+// 被时钟中断打断后中断处理中再分配内存就会死锁，因此改用关中断的 SpinLock
+struct KernelHeap(SpinLock<Heap<32>>);
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0
+            .lock()
+            .alloc(layout)
+            .map_or(null_mut(), |ptr| ptr.as_ptr())
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+}
 
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap<32> = LockedHeap::<32>::empty();
+static HEAP_ALLOCATOR: KernelHeap = KernelHeap(SpinLock::new(Heap::<32>::empty()));
 
 static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 
 pub fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
+            .0
             .lock()
-            .init(HEAP_SPACE.as_ptr() as usize, KERNEL_STACK_SIZE);
+            .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
 }
 
@@ -20,6 +41,7 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
     panic!("Heap allocation error, layout={:?}", layout);
 }
 
+#[cfg(feature = "kernel_test")]
 pub fn heap_test() {
     use alloc::boxed::Box;
     use alloc::vec::Vec;
@@ -1,16 +1,24 @@
-use core::{arch::asm, borrow::BorrowMut};
+use core::{arch::asm, borrow::BorrowMut, ops::Bound};
 
 use alloc::{collections::BTreeMap, vec::Vec};
 use riscv::register::satp;
 
-use crate::config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
+use crate::config::{MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE, USER_STACK_TOP};
 
 use super::{
     address::{PhysAddr, PhysPageNum, SimpleRange, VirtAddr, VirtPageNum},
-    frame_allocator::{frame_alloc, FrameTracker},
-    page_table::{PTEFlags, PageTable, PageTableEntry},
+    frame_allocator::{frame_alloc, frame_free_count, frame_ref_count, FrameTracker},
+    page_table::{PTEFlags, PageSize, PageTable, PageTableEntry},
+    swap::{swap_alloc, SwapSlot},
 };
 
+// 处理缺页时保持的最少空闲页帧数，不足时换出用户页
+// 缺页只在换出之后仍有这么多空闲页帧时才分配，剩下的页帧留给内核栈、fork 等不能换出页的分配，
+// 也给多个 hart 同时处理缺页时的分配留出余量
+const MIN_FREE_FRAMES: usize = 64;
+// 解除映射的页数超过这个值时刷新整个 TLB，而不是逐页刷新
+const FLUSH_ALL_PAGES: usize = 64;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MapType {
     Identifier,
@@ -35,7 +43,6 @@ extern "C" {
     fn edata();
     fn sbss_with_stack();
     fn ebss();
-    fn ekernel();
     fn strampoline();
 }
 
@@ -44,6 +51,11 @@ pub struct MapArea {
     data_frames: BTreeMap<VirtPageNum, FrameTracker>,
     map_type: MapType,
     map_perm: MapPermission,
+    // 区域的初始内容，从区域的第一页开始，其余部分为 0
+    init_data: Option<&'static [u8]>,
+    // 页在交换区中的副本：不在 data_frames 中的页已被换出，
+    // 仍在 data_frames 中的页只要没有被写过（D 位为 0），副本就与页帧的内容一致
+    swap_slots: BTreeMap<VirtPageNum, SwapSlot>,
 }
 
 impl MapArea {
@@ -60,51 +72,188 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            init_data: None,
+            swap_slots: BTreeMap::new(),
+        }
+    }
+    // 复制一个区域的元信息（范围、类型、权限），不复制页帧
+    pub fn from_another(another: &MapArea) -> Self {
+        Self {
+            vpn_range: SimpleRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
+            data_frames: BTreeMap::new(),
+            map_type: another.map_type,
+            map_perm: another.map_perm,
+            init_data: another.init_data,
+            swap_slots: BTreeMap::new(),
+        }
+    }
+    // 用户可以访问的 Framed 区域按需分配页帧：映射时页表项保持无效，第一次访问时在缺页中分配
+    // 内核使用的区域（内核栈、trap 上下文）在内核中访问，不能缺页，仍然立即分配
+    fn is_lazy(&self) -> bool {
+        self.map_type == MapType::Framed && self.map_perm.contains(MapPermission::U)
+    }
+    // 恒等映射的区域从头开始每次使用对齐允许的最大的页，返回每一页的起始位置与大小
+    fn identical_pages(&self) -> Vec<(VirtPageNum, PageSize)> {
+        let end = self.vpn_range.get_end();
+        let mut pages = Vec::new();
+        let mut vpn = self.vpn_range.get_start();
+        while vpn < end {
+            let size = PageSize::largest(vpn, end);
+            pages.push((vpn, size));
+            vpn = VirtPageNum(vpn.0 + size.pages());
+        }
+        pages
+    }
+    fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    fn overlaps(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        self.vpn_range.get_start() < end && start < self.vpn_range.get_end()
+    }
+    // 将区域从 at 处分成两段，自身保留 [start, at)，返回 [at, end)，页帧随之划分
+    fn split_off(&mut self, at: VirtPageNum) -> MapArea {
+        let end = self.vpn_range.get_end();
+        self.vpn_range = SimpleRange::new(self.vpn_range.get_start(), at);
+        Self {
+            vpn_range: SimpleRange::new(at, end),
+            data_frames: self.data_frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            init_data: self.init_data.map(|data| {
+                let offset = (at.0 - self.vpn_range.get_start().0) << PAGE_SIZE;
+                &data[offset.min(data.len())..]
+            }),
+            swap_slots: self.swap_slots.split_off(&at),
+        }
+    }
+    // 在区域的末尾追加页，使其结束于 new_end
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
+        if !self.is_lazy() {
+            for vpn in SimpleRange::new(self.vpn_range.get_end(), new_end) {
+                self.map_one(page_table, vpn);
+            }
+        }
+        self.vpn_range = SimpleRange::new(self.vpn_range.get_start(), new_end);
+    }
+    // 释放区域末尾的页，使其结束于 new_end
+    pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
+        for vpn in SimpleRange::new(new_end, self.vpn_range.get_end()) {
+            self.unmap_one(page_table, vpn);
         }
+        self.vpn_range = SimpleRange::new(self.vpn_range.get_start(), new_end);
     }
     pub fn map(&mut self, page_table: &mut PageTable) {
+        if self.is_lazy() {
+            return;
+        }
+        if self.map_type == MapType::Identifier {
+            for (vpn, size) in self.identical_pages() {
+                page_table.map_huge(vpn, PhysPageNum(vpn.0), self.pte_flags(), size);
+            }
+            return;
+        }
         for vpn in self.vpn_range {
-            let ppn;
-            // 映射到帧
-            match self.map_type {
-                MapType::Identifier => {
-                    ppn = PhysPageNum(vpn.0);
-                }
-                MapType::Framed => {
-                    if let Some(frame) = frame_alloc() {
-                        ppn = frame.ppn;
-                        self.data_frames.insert(vpn, frame);
-                    } else {
-                        panic!("[kernel] frame_alloc error when map MapArea");
-                    }
+            self.map_one(page_table, vpn);
+        }
+    }
+    fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        let ppn;
+        // 映射到帧
+        match self.map_type {
+            MapType::Identifier => {
+                ppn = PhysPageNum(vpn.0);
+            }
+            MapType::Framed => {
+                if let Some(frame) = frame_alloc() {
+                    ppn = frame.ppn;
+                    self.fill_frame(vpn, ppn);
+                    self.data_frames.insert(vpn, frame);
+                } else {
+                    panic!("[kernel] frame_alloc error when map MapArea");
                 }
             }
-            let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
-            // 记录到页表
-            page_table.map(vpn, ppn, pte_flags);
         }
+        // 记录到页表
+        page_table.map(vpn, ppn, self.pte_flags());
+    }
+    // 新映射的页视为刚被访问过，在换出时的时钟算法中有一次机会被跳过
+    fn pte_flags(&self) -> PTEFlags {
+        PTEFlags::from_bits(self.map_perm.bits() as u16).unwrap() | PTEFlags::A
+    }
+    // 写入一个写时复制的页：页帧仍被共享时复制一份，否则直接恢复写权限
+    fn copy_on_write(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        let frame = &self.data_frames[&vpn];
+        if frame_ref_count(frame.ppn) > 1 {
+            let new_frame = frame_alloc().expect("[kernel] frame_alloc error when copy on write");
+            new_frame
+                .ppn
+                .get_page_array()
+                .copy_from_slice(frame.ppn.get_page_array());
+            page_table.remap(vpn, new_frame.ppn, self.pte_flags());
+            self.data_frames.insert(vpn, new_frame);
+        } else {
+            page_table.remap(vpn, frame.ppn, self.pte_flags());
+        }
+        flush_tlb(vpn);
+    }
+    // 将 vpn 这一页换出到交换区并释放页帧，dirty 为页表项的 D 位
+    // 交换区中已有的副本没有过时并且页没有被写过时不需要再写一次，交换区已满时返回 false
+    fn swap_out(&mut self, page_table: &mut PageTable, vpn: VirtPageNum, dirty: bool) -> bool {
+        if dirty || !self.swap_slots.contains_key(&vpn) {
+            // 与其他进程共享的副本不能改写，需要换到新的位置
+            let slot = match self.swap_slots.remove(&vpn) {
+                Some(slot) if !slot.is_shared() => slot,
+                _ => match swap_alloc() {
+                    Some(slot) => slot,
+                    None => return false,
+                },
+            };
+            slot.write(self.data_frames[&vpn].ppn);
+            self.swap_slots.insert(vpn, slot);
+        }
+        page_table.mark_swapped(vpn);
+        flush_tlb(vpn);
+        self.data_frames.remove(&vpn);
+        true
+    }
+    // 将换出的页读回新分配的页帧，交换区中的副本保留到页被写过之后
+    fn swap_in(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        let frame = frame_alloc().expect("[kernel] frame_alloc error when swap in");
+        self.swap_slots[&vpn].read(frame.ppn);
+        page_table.map(vpn, frame.ppn, self.pte_flags());
+        self.data_frames.insert(vpn, frame);
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::Identifier {
+            for (vpn, size) in self.identical_pages() {
+                page_table.unmap_huge(vpn, size);
+            }
+            return;
+        }
         for vpn in self.vpn_range {
-            if self.map_type == MapType::Framed {
-                self.data_frames.remove(&vpn);
+            self.unmap_one(page_table, vpn);
+        }
+    }
+    fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        // 按需分配的区域中还没有访问过的页没有映射，换出的页只需要释放交换区中的位置
+        if self.map_type == MapType::Framed && self.data_frames.remove(&vpn).is_none() {
+            if self.swap_slots.remove(&vpn).is_some() {
+                page_table.unmap_swapped(vpn);
             }
-            // TODO: 理论上传入的 page_table 不是 mut 的会报错，但是这里没有
-            page_table.unmap(vpn);
+            return;
         }
+        self.swap_slots.remove(&vpn);
+        // TODO: 理论上传入的 page_table 不是 mut 的会报错，但是这里没有
+        page_table.unmap(vpn);
     }
-    // aligned when load data to memory
-    pub fn load_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
-        assert_eq!(self.map_type, MapType::Framed);
-        // 采用逐字节写入的逻辑，一个内存地址对应一个字节，我们的页表逻辑决定了内存是 4KiB 对齐的(offset 是12位)，一个物理页帧是 4KiB
-        for start in (1..data.len()).step_by(1 << PAGE_SIZE) {
-            let src = &data[start..data.len().min(start + (1 << PAGE_SIZE))];
-            let mut current_vpn = self.vpn_range.get_start();
-            let dst =
-                page_table.get_pte(current_vpn).ppn().get_page_array()[..src.len()].borrow_mut();
-            dst.copy_from_slice(src);
-            // 一个页表项映射完成
-            current_vpn.0 += current_vpn.0 + 1;
+    // 将初始内容中属于 vpn 这一页的部分复制到新分配的页帧中
+    fn fill_frame(&self, vpn: VirtPageNum, ppn: PhysPageNum) {
+        if let Some(data) = self.init_data {
+            let start = (vpn.0 - self.vpn_range.get_start().0) << PAGE_SIZE;
+            if start < data.len() {
+                let src = &data[start..data.len().min(start + (1 << PAGE_SIZE))];
+                ppn.get_page_array()[..src.len()].copy_from_slice(src);
+            }
         }
     }
 }
@@ -115,6 +264,10 @@ pub struct MemorySet {
     page_table: PageTable,
     // 应用程序眼中的内存空间
     areas: Vec<MapArea>,
+    // 换出时时钟算法的指针，指向上一次检查过的页
+    clock_hand: VirtPageNum,
+    // 内核正在转换的用户缓冲区所在的页 [start, end)，换出时跳过
+    pinned: Option<(VirtPageNum, VirtPageNum)>,
 }
 
 impl MemorySet {
@@ -122,16 +275,16 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            clock_hand: VirtPageNum(0),
+            pinned: None,
         }
     }
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
+    fn push(&mut self, mut map_area: MapArea, data: Option<&'static [u8]>) {
+        map_area.init_data = data;
         map_area.map(self.page_table.borrow_mut());
-        if let Some(data) = data {
-            map_area.load_data(self.page_table.borrow_mut(), data);
-        }
         self.areas.push(map_area);
     }
     pub fn activate(&self) {
@@ -142,7 +295,7 @@ impl MemorySet {
         }
     }
 
-    pub fn push_kernel_stack_for_app(
+    pub fn insert_framed_area(
         &mut self,
         start: VirtAddr,
         end: VirtAddr,
@@ -151,6 +304,124 @@ impl MemorySet {
         self.push(MapArea::new(start, end, MapType::Framed, permission), None);
     }
 
+    pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
+        if let Some((idx, area)) = self
+            .areas
+            .iter_mut()
+            .enumerate()
+            .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
+        {
+            area.unmap(&mut self.page_table);
+            self.areas.remove(idx);
+        }
+    }
+
+    // 将起始于 start 的区域扩展到 new_end，与其他区域重叠时返回 false
+    pub fn append_to(&mut self, start: VirtPageNum, new_end: VirtPageNum) -> bool {
+        let idx = match self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start)
+        {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let end = self.areas[idx].vpn_range.get_end();
+        if self
+            .areas
+            .iter()
+            .enumerate()
+            .any(|(i, area)| i != idx && area.overlaps(end, new_end))
+        {
+            return false;
+        }
+        self.areas[idx].append_to(&mut self.page_table, new_end);
+        true
+    }
+
+    // 将起始于 start 的区域缩小到 new_end
+    pub fn shrink_to(&mut self, start: VirtPageNum, new_end: VirtPageNum) -> bool {
+        match self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() == start)
+        {
+            Some(area) => {
+                let old_end = area.vpn_range.get_end();
+                area.shrink_to(&mut self.page_table, new_end);
+                flush_tlb_range(new_end, old_end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 映射 [start, end) 的匿名内存，与已有的区域重叠时返回 false
+    pub fn mmap(
+        &mut self,
+        start: VirtPageNum,
+        end: VirtPageNum,
+        permission: MapPermission,
+    ) -> bool {
+        if self.areas.iter().any(|area| area.overlaps(start, end)) {
+            return false;
+        }
+        self.push(
+            MapArea::new(start.into(), end.into(), MapType::Framed, permission),
+            None,
+        );
+        true
+    }
+
+    // 解除 [start, end) 的映射，范围内有未映射的页时返回 false
+    // 只覆盖了一个区域的一部分时，该区域被切分，剩下的部分保持映射
+    pub fn munmap(&mut self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        // 按区域而不是逐页检查，len 由用户给出，可能非常大
+        let mut ranges: Vec<(VirtPageNum, VirtPageNum)> = self
+            .areas
+            .iter()
+            .filter(|area| area.overlaps(start, end))
+            .map(|area| (area.vpn_range.get_start(), area.vpn_range.get_end()))
+            .collect();
+        ranges.sort_unstable();
+        let mut covered = start;
+        for (area_start, area_end) in ranges {
+            if area_start > covered {
+                break;
+            }
+            covered = covered.max(area_end);
+        }
+        if covered < end {
+            return false;
+        }
+        let areas = core::mem::take(&mut self.areas);
+        for mut area in areas {
+            if !area.overlaps(start, end) {
+                self.areas.push(area);
+                continue;
+            }
+            // 切成 [area_start, start)、[start, end)、[end, area_end) 三段，只解除中间一段
+            let mut middle = if area.vpn_range.get_start() < start {
+                let middle = area.split_off(start);
+                self.areas.push(area);
+                middle
+            } else {
+                area
+            };
+            if end < middle.vpn_range.get_end() {
+                self.areas.push(middle.split_off(end));
+            }
+            middle.unmap(&mut self.page_table);
+        }
+        flush_tlb_range(start, end);
+        true
+    }
+
+    // 释放所有区域占用的页帧，页表本身的页帧随 MemorySet 一起释放
+    pub fn recycle_data_pages(&mut self) {
+        self.areas.clear();
+    }
+
     pub fn new_kernel() -> Self {
         let mut memory_set = Self::new_bare();
         //TODO: map trampoline
@@ -213,21 +484,36 @@ impl MemorySet {
             ),
             None,
         );
-        // ekernel map
-        println!("mapping ekernel");
-        memory_set.push(
-            MapArea::new(
-                (ekernel as usize).into(),
-                MEMORY_END.into(),
-                MapType::Identifier,
-                MapPermission::R | MapPermission::X,
-            ),
-            None,
-        );
+        // 可以分配的物理内存：所有页帧都在其中，内核通过恒等映射读写
+        println!("mapping physical memory");
+        for (start, end) in super::memory_regions() {
+            memory_set.push(
+                MapArea::new(
+                    start.into(),
+                    end.into(),
+                    MapType::Identifier,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            );
+        }
+        println!("mapping memory-mapped registers");
+        for &(start, len) in MMIO {
+            memory_set.push(
+                MapArea::new(
+                    start.into(),
+                    (start + len).into(),
+                    MapType::Identifier,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            );
+        }
         memory_set
     }
 
-    pub fn load_elf(elf_data: &[u8]) -> Result<(Self, usize, usize), &str> {
+    // 返回地址空间、用户栈顶、堆的起始地址以及入口地址
+    pub fn load_elf(elf_data: &'static [u8]) -> Result<(Self, usize, usize, usize), &'static str> {
         let mut memory_set = Self::new_bare();
         //TODO: map trampoline
         memory_set.map_trampoline();
@@ -258,7 +544,7 @@ impl MemorySet {
                 }
 
                 let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
+                max_end_vpn = max_end_vpn.max(map_area.vpn_range.get_end());
                 memory_set.push(
                     map_area,
                     Some(
@@ -268,13 +554,21 @@ impl MemorySet {
                 );
             }
         }
-        let mut user_stack_bottom: usize = VirtAddr::from(max_end_vpn).into();
-
-        // guard page: 4KiB  leave space for it
-        user_stack_bottom += 1 << PAGE_SIZE;
+        // 堆紧跟在 ELF 的各个段之后，初始大小为 0，由 sbrk 扩展
+        let user_heap_bottom: usize = VirtAddr::from(max_end_vpn).into();
+        memory_set.push(
+            MapArea::new(
+                user_heap_bottom.into(),
+                user_heap_bottom.into(),
+                MapType::Framed,
+                MapPermission::R | MapPermission::W | MapPermission::U,
+            ),
+            None,
+        );
 
-        // map user stack 低266GiB
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        // map user stack 低256GiB 的顶端，下方未映射的页作为 guard page
+        let user_stack_top = USER_STACK_TOP;
+        let user_stack_bottom = user_stack_top - USER_STACK_SIZE;
         memory_set.push(
             MapArea::new(
                 user_stack_bottom.into(),
@@ -289,7 +583,7 @@ impl MemorySet {
         memory_set.push(
             MapArea::new(
                 TRAP_CONTEXT.into(),
-                TRAP_CONTEXT.into(),
+                TRAMPOLINE.into(),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W,
             ),
@@ -298,10 +592,169 @@ impl MemorySet {
         Ok((
             memory_set,
             user_stack_top,
+            user_heap_bottom,
             elf.header.pt2.entry_point() as usize,
         ))
     }
 
+    // fork 时使用：按区域复制一个用户地址空间
+    // 用户区域中已经分配的页帧由父子进程共享，可写的页在双方都改为只读并标记为写时复制，
+    // 换出的页共享交换区中的副本，没有分配的页在子进程中同样按需分配；
+    // trap 上下文等内核使用的区域直接复制
+    pub fn from_existed_user(user_space: &mut MemorySet) -> MemorySet {
+        let mut memory_set = Self::new_bare();
+        memory_set.map_trampoline();
+        for area in user_space.areas.iter() {
+            let mut new_area = MapArea::from_another(area);
+            if area.is_lazy() {
+                for (&vpn, frame) in area.data_frames.iter() {
+                    let dirty = user_space.page_table.get_pte(vpn).flags().contains(PTEFlags::D);
+                    let mut flags = area.pte_flags();
+                    if flags.contains(PTEFlags::W) {
+                        flags = (flags - PTEFlags::W) | PTEFlags::COW;
+                        user_space.page_table.remap(vpn, frame.ppn, flags);
+                    }
+                    memory_set.page_table.map(vpn, frame.ppn, flags);
+                    new_area.data_frames.insert(vpn, frame.clone());
+                    // 子进程的页表项 D 位为 0，只有与页帧内容一致的副本可以共享
+                    if !dirty {
+                        if let Some(slot) = area.swap_slots.get(&vpn) {
+                            new_area.swap_slots.insert(vpn, slot.clone());
+                        }
+                    }
+                }
+                for (&vpn, slot) in area.swap_slots.iter() {
+                    if !area.data_frames.contains_key(&vpn) {
+                        memory_set.page_table.mark_swapped(vpn);
+                        new_area.swap_slots.insert(vpn, slot.clone());
+                    }
+                }
+            } else {
+                new_area.map(&mut memory_set.page_table);
+                for (&vpn, src_frame) in area.data_frames.iter() {
+                    new_area.data_frames[&vpn]
+                        .ppn
+                        .get_page_array()
+                        .copy_from_slice(src_frame.ppn.get_page_array());
+                }
+            }
+            memory_set.areas.push(new_area);
+        }
+        // 父进程的地址空间正在使用，TLB 中可能还有可写的旧表项
+        unsafe {
+            asm!("sfence.vma");
+        }
+        memory_set
+    }
+
+    // 处理用户地址空间中的缺页，vpn 需要位于按需分配的区域中，并且区域具有 access 要求的权限
+    // 还没有分配页帧时分配页帧，被换出时换入，是写时复制的页时复制一份，处理成功返回 true
+    pub fn handle_page_fault(&mut self, vpn: VirtPageNum, access: MapPermission) -> bool {
+        let idx = match self.areas.iter().position(|area| area.contains(vpn)) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let area = &self.areas[idx];
+        if !area.is_lazy() || !area.map_perm.contains(access) {
+            return false;
+        }
+        if area.data_frames.contains_key(&vpn)
+            && !self.page_table.get_pte(vpn).flags().contains(PTEFlags::COW)
+        {
+            return false;
+        }
+        // 内存不足时先换出其他页，换出之后仍然不足时处理失败，由调用者杀死当前任务
+        // 换出的可能恰好是这一页，因此下面重新判断页的状态
+        if !self.reserve_frames() {
+            println!(
+                "[kernel] out of memory when handling page fault at {:#x}",
+                VirtAddr::from(vpn).0
+            );
+            return false;
+        }
+        let area = &mut self.areas[idx];
+        if area.data_frames.contains_key(&vpn) {
+            area.copy_on_write(&mut self.page_table, vpn);
+        } else if area.swap_slots.contains_key(&vpn) {
+            area.swap_in(&mut self.page_table, vpn);
+        } else {
+            area.map_one(&mut self.page_table, vpn);
+        }
+        true
+    }
+
+    // 固定 [start, end) 中的页，直到调用 unpin 之前都不会被换出
+    // 内核转换跨越多页的用户缓冲区时使用，避免为后面的页处理缺页时换出前面已经转换好的页
+    pub fn pin(&mut self, start: VirtPageNum, end: VirtPageNum) {
+        self.pinned = Some((start, end));
+    }
+    pub fn unpin(&mut self) {
+        self.pinned = None;
+    }
+    fn is_pinned(&self, vpn: VirtPageNum) -> bool {
+        matches!(self.pinned, Some((start, end)) if start <= vpn && vpn < end)
+    }
+
+    // 换出当前地址空间中的页，直到有 MIN_FREE_FRAMES 个空闲页帧，没有页可以换出时返回 false
+    // 内核在系统调用中通过物理地址读写用户缓冲区，换出这些页会让内核写入已经释放的页帧：
+    // - 只在当前地址空间中选择换出的页，其他任务可能正在系统调用中读写自己的页
+    // - 当前任务正在转换的缓冲区的页被固定，不会被换出；转换完成之后，
+    //   系统调用在使用缓冲区期间不会再触发当前任务的缺页，这些页会一直驻留
+    fn reserve_frames(&mut self) -> bool {
+        while frame_free_count() < MIN_FREE_FRAMES {
+            if !self.swap_out_one() {
+                return false;
+            }
+        }
+        true
+    }
+
+    // 使用时钟算法选择一页换出：从 clock_hand 开始依次检查驻留在内存中的用户页，
+    // A 位为 1 的页清除 A 位后跳过，遇到的第一个 A 位为 0 的页被换出
+    // 与其他进程共享的页帧换出之后也不能释放，不参与选择
+    fn swap_out_one(&mut self) -> bool {
+        let resident: usize = self.areas.iter().map(|area| area.data_frames.len()).sum();
+        // 最多转两圈：第一圈清除所有的 A 位，第二圈一定能找到 A 位为 0 的页
+        for _ in 0..2 * resident {
+            let (idx, vpn) = match self.next_resident(self.clock_hand) {
+                Some(next) => next,
+                None => return false,
+            };
+            self.clock_hand = vpn;
+            if self.is_pinned(vpn) {
+                continue;
+            }
+            let area = &mut self.areas[idx];
+            if frame_ref_count(area.data_frames[&vpn].ppn) > 1 {
+                continue;
+            }
+            let flags = self.page_table.get_pte(vpn).flags();
+            if flags.contains(PTEFlags::A) {
+                self.page_table.clear_accessed(vpn);
+                flush_tlb(vpn);
+                continue;
+            }
+            return area.swap_out(&mut self.page_table, vpn, flags.contains(PTEFlags::D));
+        }
+        false
+    }
+
+    // 按虚拟页号的顺序找到 hand 之后的下一个驻留在内存中的用户页，到达末尾之后从头开始
+    fn next_resident(&self, hand: VirtPageNum) -> Option<(usize, VirtPageNum)> {
+        let find = |lower: Bound<&VirtPageNum>| {
+            self.areas
+                .iter()
+                .enumerate()
+                .filter(|(_, area)| area.is_lazy())
+                .filter_map(|(idx, area)| {
+                    let (&vpn, _) = area.data_frames.range((lower, Bound::Unbounded)).next()?;
+                    Some((idx, vpn))
+                })
+                .min_by_key(|&(_, vpn)| vpn)
+        };
+        find(Bound::Excluded(&hand)).or_else(|| find(Bound::Unbounded))
+    }
+
     fn map_trampoline(&mut self) {
         self.page_table.map(
             VirtAddr::from(TRAMPOLINE).into(),
@@ -314,8 +767,9 @@ impl MemorySet {
     }
 }
 
+#[cfg(feature = "kernel_test")]
 pub fn test_remap_mem() {
-    let mut kernel_space = super::KERNEL_SPACE.exclusive_access();
+    let kernel_space = super::KERNEL_SPACE.lock();
     let mid_text = VirtAddr::from((stext as usize + etext as usize) / 2);
     let mid_rodata = VirtAddr::from((srodata as usize + erodata as usize) / 2);
     let mid_data = VirtAddr::from((sdata as usize + edata as usize) / 2);
@@ -325,5 +779,51 @@ pub fn test_remap_mem() {
         .get_pte(mid_rodata.floor())
         .writable());
     assert!(!kernel_space.page_table.get_pte(mid_data.floor()).writable());
+    // 可以分配的物理内存用大页恒等映射，其中的地址同样转换到自身
+    for (start, end) in super::memory_regions() {
+        let mid = VirtAddr::from((start + end) / 2 + 0x123);
+        assert_eq!(
+            kernel_space.page_table.translate_va(mid).map(|pa| pa.0),
+            Some(mid.0)
+        );
+    }
+    drop(kernel_space);
+    // 在单独的页表中检查 2MiB 页：其中的每一页都能转换，解除映射时大小需要一致
+    let mut page_table = PageTable::new();
+    let vpn = VirtPageNum(PageSize::Size2M.pages() * 3);
+    let ppn = PhysPageNum(PageSize::Size2M.pages() * 5);
+    page_table.map_huge(vpn, ppn, PTEFlags::R | PTEFlags::W, PageSize::Size2M);
+    assert_eq!(
+        page_table.translate(VirtPageNum(vpn.0 + 7)).map(|ppn| ppn.0),
+        Some(ppn.0 + 7)
+    );
+    assert!(page_table.try_unmap_huge(vpn, PageSize::Size4K).is_err());
+    assert!(page_table.try_unmap_huge(vpn, PageSize::Size1G).is_err());
+    assert!(page_table
+        .try_unmap_huge(VirtPageNum(vpn.0 + 1), PageSize::Size2M)
+        .is_err());
+    assert!(page_table.translate(vpn).is_some());
+    assert!(page_table.try_unmap_huge(vpn, PageSize::Size2M).is_ok());
+    assert!(page_table.translate(vpn).is_none());
     println!("test remap_mem passed!");
 }
+
+// 刷新当前 hart 上 vpn 对应的 TLB 表项
+fn flush_tlb(vpn: VirtPageNum) {
+    unsafe {
+        asm!("sfence.vma {}", in(reg) VirtAddr::from(vpn).0);
+    }
+}
+
+// 刷新当前 hart 上 [start, end) 对应的 TLB 表项，页数较多时直接刷新整个 TLB
+fn flush_tlb_range(start: VirtPageNum, end: VirtPageNum) {
+    if end.0.saturating_sub(start.0) > FLUSH_ALL_PAGES {
+        unsafe {
+            asm!("sfence.vma");
+        }
+    } else {
+        for vpn in SimpleRange::new(start, end) {
+            flush_tlb(vpn);
+        }
+    }
+}
@@ -1,19 +1,92 @@
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 
-use crate::{sync::UPSafeCell, mm::memory_set::MemorySet};
+use crate::{config::PAGE_SIZE, fdt, sync::SpinLock, mm::memory_set::MemorySet};
 
 pub(crate) mod address;
 mod frame_allocator;
 mod heap_allocater;
 pub(crate) mod memory_set;
 pub(crate) mod page_table;
+mod swap;
+
+pub use frame_allocator::{frame_alloc_contiguous, FrameRangeTracker};
 
 lazy_static::lazy_static! {
-    pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> = Arc::new(unsafe {UPSafeCell::new(MemorySet::new_kernel())});
+    pub static ref KERNEL_SPACE: Arc<SpinLock<MemorySet>> = Arc::new(SpinLock::new(MemorySet::new_kernel()));
+}
+
+// Sv39 中恒等映射只能覆盖低 256GiB 的物理地址，更高的内存不使用
+const PHYS_LIMIT: usize = 1 << 38;
+
+// 可以分配给页帧的物理内存，每一段为按页对齐的 [起始地址, 结束地址)，启动时从设备树中获取
+static MEMORY_REGIONS: SpinLock<Vec<(usize, usize)>> = SpinLock::new(Vec::new());
+
+pub fn memory_regions() -> Vec<(usize, usize)> {
+    MEMORY_REGIONS.lock().clone()
 }
 
-pub fn init(){
+// 使用设备树中的所有内存区域，去掉其中保留的部分，需要在建立页表之前调用，此时可以直接访问 DTB
+// 包含内核的区域中内核之前的部分属于 SBI 固件，设备树不一定将其标记为保留，因此只使用内核之后的部分
+fn init_memory_regions(dtb: usize) {
+    extern "C" {
+        fn skernel();
+        fn ekernel();
+    }
+    let info = fdt::memory_info(dtb);
+    let kernel = skernel as usize;
+    assert!(
+        info.regions
+            .iter()
+            .any(|&(start, size)| start <= kernel && kernel < start + size),
+        "[kernel] no memory region contains the kernel"
+    );
+    let mut regions: Vec<(usize, usize)> = info
+        .regions
+        .iter()
+        .map(|&(start, size)| {
+            let end = (start + size).min(PHYS_LIMIT);
+            if start <= kernel && kernel < start + size {
+                (ekernel as usize, end)
+            } else {
+                (start, end)
+            }
+        })
+        .collect();
+    for &(hole_start, size) in info.reserved.iter() {
+        let hole_end = hole_start + size;
+        regions = regions
+            .into_iter()
+            .flat_map(|(start, end)| [(start, end.min(hole_start)), (start.max(hole_end), end)])
+            .collect();
+    }
+    let page = 1 << PAGE_SIZE;
+    let mut regions: Vec<(usize, usize)> = regions
+        .into_iter()
+        .map(|(start, end)| ((start + page - 1) & !(page - 1), end & !(page - 1)))
+        .filter(|&(start, end)| start < end)
+        .collect();
+    regions.sort_unstable();
+    for &(start, end) in regions.iter() {
+        println!("[kernel] memory: [{:#x}, {:#x})", start, end);
+    }
+    *MEMORY_REGIONS.lock() = regions;
+}
+
+pub fn init(dtb: usize){
     heap_allocater::init_heap();
+    init_memory_regions(dtb);
     frame_allocator::init_frame_allocator();
-    KERNEL_SPACE.exclusive_access().activate();
-}
\ No newline at end of file
+    KERNEL_SPACE.lock().activate();
+    #[cfg(feature = "kernel_test")]
+    {
+        heap_allocater::heap_test();
+        frame_allocator::frame_allocator_test();
+        memory_set::test_remap_mem();
+    }
+    swap::init_swap();
+}
+
+// 其余的 hart 只需要切换到已经建立好的内核地址空间
+pub fn init_secondary(){
+    KERNEL_SPACE.lock().activate();
+}
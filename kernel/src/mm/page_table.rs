@@ -1,16 +1,18 @@
 use core::borrow::BorrowMut;
 
-use alloc::vec::Vec;
+use alloc::{string::String, vec::Vec};
 
 use crate::config::PAGE_SIZE;
+use crate::task::{handle_current_page_fault, pin_current_pages, unpin_current_pages};
 
 use super::{
-    address::{PhysPageNum, StepByOne, VirtAddr, VirtPageNum},
+    address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum},
     frame_allocator::{frame_alloc, FrameTracker},
+    memory_set::MapPermission,
 };
 
 bitflags::bitflags! {
-    pub struct PTEFlags: u8 {
+    pub struct PTEFlags: u16 {
         const V = 1 << 0;
         const R = 1 << 1;
         const W = 1 << 2;
@@ -19,6 +21,10 @@ bitflags::bitflags! {
         const G = 1 << 5;
         const A = 1 << 6;
         const D = 1 << 7;
+        // RSW 位，由软件使用：写时复制的页，写入时需要先复制一份
+        const COW = 1 << 8;
+        // RSW 位，由软件使用：V 为 0 的叶子页表项中表示该页被换出到了交换区
+        const SWAPPED = 1 << 9;
     }
 }
 
@@ -43,7 +49,7 @@ impl PageTableEntry {
     }
 
     pub fn flags(&self) -> PTEFlags {
-        PTEFlags::from_bits_truncate(self.bits as u8)
+        PTEFlags::from_bits_truncate(self.bits as u16)
     }
 
     pub fn set_pte(&mut self, ppn: PhysPageNum, flags: PTEFlags) {
@@ -58,6 +64,10 @@ impl PageTableEntry {
         // V 非零有效
         !(self.flags() & PTEFlags::V).is_empty()
     }
+    // R、W、X 不全为 0 的有效页表项是叶子，否则指向下一级页表
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid() && self.flags().intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X)
+    }
     pub fn readable(&self) -> bool {
         (self.flags() & PTEFlags::R) != PTEFlags::empty()
     }
@@ -69,6 +79,40 @@ impl PageTableEntry {
     }
 }
 
+// 叶子页表项所在的级别决定了页的大小：根页表中为 1GiB，第二级为 2MiB，最后一级为 4KiB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSize {
+    Size4K,
+    Size2M,
+    Size1G,
+}
+
+impl PageSize {
+    // 包含的 4KiB 页数
+    pub fn pages(self) -> usize {
+        match self {
+            PageSize::Size4K => 1,
+            PageSize::Size2M => 1 << 9,
+            PageSize::Size1G => 1 << 18,
+        }
+    }
+    // 叶子页表项所在的级别，根页表为 0
+    fn level(self) -> usize {
+        match self {
+            PageSize::Size1G => 0,
+            PageSize::Size2M => 1,
+            PageSize::Size4K => 2,
+        }
+    }
+    // 从 vpn 开始映射 [vpn, end) 时可以使用的最大的页，vpn 需要按页的大小对齐
+    pub fn largest(vpn: VirtPageNum, end: VirtPageNum) -> Self {
+        [PageSize::Size1G, PageSize::Size2M]
+            .into_iter()
+            .find(|size| vpn.0 % size.pages() == 0 && vpn.0 + size.pages() <= end.0)
+            .unwrap_or(PageSize::Size4K)
+    }
+}
+
 pub struct PageTable {
     root_ppn: PhysPageNum,
     frames: Vec<FrameTracker>,
@@ -84,14 +128,14 @@ impl PageTable {
         }
     }
 
-    fn crete_pte(&mut self, vpn: VirtPageNum) -> Result<&mut PageTableEntry, &str> {
+    // 找到 size 大小的页对应的叶子页表项，沿途缺少的页表会被分配
+    fn crete_pte(&mut self, vpn: VirtPageNum, size: PageSize) -> Result<&mut PageTableEntry, &str> {
         let indexes = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result = None;
-        for i in 0..3 {
+        for i in 0..size.level() {
             let pte = ppn.get_pte_entry()[indexes[i]].borrow_mut();
-            if i == 2 && pte.is_valid() {
-                return Err("entry has created");
+            if pte.is_leaf() {
+                return Err("entry has been mapped by a huge page");
             }
             if !pte.is_valid() {
                 let frame_tracker = frame_alloc().unwrap();
@@ -100,64 +144,150 @@ impl PageTable {
                 self.frames.push(frame_tracker);
             }
             ppn = pte.ppn();
-            result = Some(pte);
         }
-        if let Some(pte) = result {
-            Ok(pte)
-        } else {
-            Err("[kernel] [page_table]get pte error")
+        // 叶子页表项由调用者填写，不需要为它分配页帧
+        let pte = ppn.get_pte_entry()[indexes[size.level()]].borrow_mut();
+        if pte.is_valid() {
+            return Err("entry has created");
         }
+        Ok(pte)
     }
 
-    fn find_pte(&self, vpn: VirtPageNum) -> Result<&mut PageTableEntry, &str> {
-        // 获取三级页表中的对应页表项
+    // 找到 vpn 所在的页的叶子页表项以及页的大小，叶子页表项可以位于任意一级
+    fn walk(&self, vpn: VirtPageNum) -> Result<(&mut PageTableEntry, PageSize), &str> {
         let indexes = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result = None;
-        for i in 0..3 {
+        let sizes = [PageSize::Size1G, PageSize::Size2M, PageSize::Size4K];
+        for (i, size) in sizes.into_iter().enumerate() {
             let pte = ppn.get_pte_entry()[indexes[i]].borrow_mut();
             if !pte.is_valid() {
                 return Err("entry is invalid, maybe vpn not mapped");
             }
-            if i == 2 {
-                result = Some(pte);
-                break;
+            if pte.is_leaf() || i == 2 {
+                return Ok((pte, size));
             }
             ppn = pte.ppn();
         }
-        if let Some(pte) = result {
-            Ok(pte)
-        } else {
-            Err("[kernel] [page_table]get pte error")
+        Err("[kernel] [page_table]get pte error")
+    }
+
+    fn find_pte(&self, vpn: VirtPageNum) -> Result<&mut PageTableEntry, &str> {
+        self.walk(vpn).map(|(pte, _)| pte)
+    }
+
+    // 获取叶子页表项，不要求其有效，中间的页表不存在时返回 None
+    fn find_leaf(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        let indexes = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        for i in 0..2 {
+            let pte = ppn.get_pte_entry()[indexes[i]].borrow_mut();
+            if !pte.is_valid() || pte.is_leaf() {
+                return None;
+            }
+            ppn = pte.ppn();
         }
+        Some(ppn.get_pte_entry()[indexes[2]].borrow_mut())
     }
 
+    // 只用于 4KiB 页，vpn 位于大页中时得到的是整个大页的页表项，因此 panic；
+    // 需要大页中某一页的物理页号时使用 translate
     pub fn get_pte(&self, vpn: VirtPageNum) -> PageTableEntry {
-        match self.find_pte(vpn) {
-            Ok(pte) => *pte,
+        match self.walk(vpn) {
+            Ok((pte, PageSize::Size4K)) => *pte,
+            Ok((_, size)) => panic!("[kernel] get_pte: mapped by {:?} page, use translate", size),
             Err(_) => panic!("[kernel] get_pte failed"),
         }
     }
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        match self.crete_pte(vpn) {
+        self.map_huge(vpn, ppn, flags, PageSize::Size4K);
+    }
+    // 使用 size 大小的页映射，vpn 与 ppn 都需要按页的大小对齐
+    pub fn map_huge(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+        size: PageSize,
+    ) {
+        assert!(
+            vpn.0 % size.pages() == 0 && ppn.0 % size.pages() == 0,
+            "[kernel] map: {:?} page is not aligned",
+            size
+        );
+        match self.crete_pte(vpn, size) {
             Ok(pte) => {
-                pte.set_pte(ppn, flags);
+                pte.set_pte(ppn, flags | PTEFlags::V);
             }
             Err(e) => {
                 panic!("[kernel] map: {}", e);
             }
         }
     }
-    pub fn unmap(&mut self, vpn: VirtPageNum) {
+    // 修改已经映射的页表项，用于写时复制，保留原有的 A、D 位
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
         match self.find_pte(vpn) {
             Ok(pte) => {
-                pte.clear();
+                let accessed_dirty = pte.flags() & (PTEFlags::A | PTEFlags::D);
+                pte.set_pte(ppn, flags | accessed_dirty | PTEFlags::V);
             }
             Err(e) => {
-                panic!("[kernel] unmap: {}", e);
+                panic!("[kernel] remap: {}", e);
             }
         }
     }
+    // 清除 A 位，用于换出时的时钟算法
+    pub fn clear_accessed(&mut self, vpn: VirtPageNum) {
+        let pte = self.find_pte(vpn).unwrap();
+        pte.set_pte(pte.ppn(), pte.flags() - PTEFlags::A);
+    }
+    // 将 vpn 标记为已换出，页表项变为无效，访问时触发缺页
+    pub fn mark_swapped(&mut self, vpn: VirtPageNum) {
+        if self.find_leaf(vpn).is_none() {
+            self.crete_pte(vpn, PageSize::Size4K).unwrap();
+        }
+        *self.find_leaf(vpn).unwrap() = PageTableEntry::new(PhysPageNum(0), PTEFlags::SWAPPED);
+    }
+    // 清除已换出的页的标记
+    pub fn unmap_swapped(&mut self, vpn: VirtPageNum) {
+        if let Some(pte) = self.find_leaf(vpn) {
+            pte.clear();
+        }
+    }
+    pub fn unmap(&mut self, vpn: VirtPageNum) {
+        self.unmap_huge(vpn, PageSize::Size4K);
+    }
+    // 解除 map_huge 建立的映射，vpn 为页的起始位置，size 需要与映射时相同
+    pub fn unmap_huge(&mut self, vpn: VirtPageNum, size: PageSize) {
+        if let Err(e) = self.try_unmap_huge(vpn, size) {
+            panic!("[kernel] unmap: {:?} page, {}", size, e);
+        }
+    }
+    // 与 unmap_huge 相同，但是参数与映射时不一致时不修改页表并返回错误
+    pub fn try_unmap_huge(&mut self, vpn: VirtPageNum, size: PageSize) -> Result<(), &str> {
+        let (pte, found) = self.walk(vpn)?;
+        if found != size {
+            return Err("but mapped by a page of another size");
+        }
+        if vpn.0 % size.pages() != 0 {
+            return Err("but vpn is not the start of the page");
+        }
+        pte.clear();
+        Ok(())
+    }
+
+    // vpn 所在的物理页，大页中的 vpn 对应其中的一个 4KiB 页
+    pub fn translate(&self, vpn: VirtPageNum) -> Option<PhysPageNum> {
+        self.walk(vpn)
+            .ok()
+            .map(|(pte, size)| PhysPageNum(pte.ppn().0 + (vpn.0 & (size.pages() - 1))))
+    }
+
+    pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
+        self.translate(va.floor()).map(|ppn| {
+            let aligned_pa: PhysAddr = ppn.into();
+            (aligned_pa.0 + va.page_offset()).into()
+        })
+    }
 
     pub fn token(&self) -> usize {
         // 激活 mmu 为 sv39 mode
@@ -171,18 +301,66 @@ impl PageTable {
         }
     }
 }
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
+// 内核访问用户地址空间之前，按需分配的页可能还没有页帧，此时由当前任务处理缺页
+// 内核通过物理地址直接读写，不经过页表的权限检查，因此在这里按 access 检查用户页的权限，
+// 写入写时复制的页之前也需要先复制一份；地址无效或者没有权限时返回 None
+// 写入时同样因为不经过页表而不会设置 D 位，需要手动设置，否则换出时会认为交换区中的旧副本仍然有效
+// 只在访问当前任务的地址空间时使用，调用时不能持有当前任务的锁
+fn translate_user_vpn(
+    page_table: &PageTable,
+    vpn: VirtPageNum,
+    access: MapPermission,
+) -> Option<PhysPageNum> {
+    let required = PTEFlags::from_bits(access.bits() as u16).unwrap() | PTEFlags::U;
+    let present = matches!(page_table.find_pte(vpn), Ok(pte) if pte.flags().contains(required));
+    if !present && !handle_current_page_fault(vpn, access) {
+        return None;
+    }
+    let pte = page_table.find_pte(vpn).ok()?;
+    if access.contains(MapPermission::W) {
+        pte.set_pte(pte.ppn(), pte.flags() | PTEFlags::A | PTEFlags::D);
+    }
+    Some(pte.ppn())
+}
 
-    let mut start = ptr as usize;
-    let end = start + len;
+fn translate_user_va(
+    page_table: &PageTable,
+    va: VirtAddr,
+    access: MapPermission,
+) -> Option<PhysAddr> {
+    translate_user_vpn(page_table, va.floor(), access)?;
+    page_table.translate_va(va)
+}
 
+// access 为内核对这段缓冲区的访问方式：读取用户数据时为 R，写入用户缓冲区时为 W
+pub fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    access: MapPermission,
+) -> Option<Vec<&'static mut [u8]>> {
+    let page_table = PageTable::from_token(token);
+    let start = ptr as usize;
+    let end = start.checked_add(len)?;
+    // 为后面的页处理缺页时可能需要换出页，固定整个缓冲区，已经转换好的页不会被换出
+    pin_current_pages(VirtAddr::from(start).floor(), VirtAddr::from(end).ceil());
+    let buffers = translate_user_range(&page_table, start, end, access);
+    unpin_current_pages();
+    buffers
+}
+
+fn translate_user_range(
+    page_table: &PageTable,
+    mut start: usize,
+    end: usize,
+    access: MapPermission,
+) -> Option<Vec<&'static mut [u8]>> {
     let mut v = Vec::new();
     while start < end {
         let start_va = VirtAddr::from(start);
 
         let mut vpn = start_va.floor();
-        let ppn = page_table.get_pte(vpn).ppn();
+        let ppn = translate_user_vpn(page_table, vpn, access)?;
         vpn.step();
 
         let mut end_va = VirtAddr::from(vpn);
@@ -195,5 +373,70 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
         }
         start = end_va.into();
     }
-    v
+    Some(v)
+}
+
+// 从用户地址空间中读取一个以 \0 结尾的字符串
+pub fn translated_str(token: usize, ptr: *const u8) -> Option<String> {
+    let page_table = PageTable::from_token(token);
+    let mut string = String::new();
+    let mut va = ptr as usize;
+    loop {
+        let ch: u8 = *(translate_user_va(&page_table, VirtAddr::from(va), MapPermission::R)?
+            .get_mut::<u8>());
+        if ch == 0 {
+            break;
+        }
+        string.push(ch as char);
+        va += 1;
+    }
+    Some(string)
+}
+
+// 用于内核写入用户地址空间中的一个值，ptr 需要对齐，这样该值不会跨越页边界
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Option<&'static mut T> {
+    if ptr as usize % core::mem::align_of::<T>() != 0 {
+        return None;
+    }
+    let page_table = PageTable::from_token(token);
+    Some(translate_user_va(&page_table, VirtAddr::from(ptr as usize), MapPermission::W)?.get_mut())
+}
+
+// 用户地址空间中一段已经完成转换的缓冲区
+// 转换时已经处理了缺页，之后的写入不会再访问当前任务，因此可以在持有任务的锁时写入
+pub struct UserBuffer {
+    start: usize,
+    buffers: Vec<&'static mut [u8]>,
+}
+
+impl UserBuffer {
+    // 内核将要写入 [ptr, ptr + len)，地址无效或者不可写时返回 None
+    pub fn new(token: usize, ptr: *const u8, len: usize) -> Option<Self> {
+        Some(Self {
+            start: ptr as usize,
+            buffers: translated_byte_buffer(token, ptr, len, MapPermission::W)?,
+        })
+    }
+
+    // 将 src 按字节写入 dst 处，dst 处的值需要完全位于该缓冲区中
+    pub fn write<T>(&mut self, dst: *mut T, src: &T) {
+        let mut src = unsafe {
+            core::slice::from_raw_parts(src as *const T as *const u8, core::mem::size_of::<T>())
+        };
+        let mut offset = dst as usize - self.start;
+        for buffer in self.buffers.iter_mut() {
+            if offset >= buffer.len() {
+                offset -= buffer.len();
+                continue;
+            }
+            let len = src.len().min(buffer.len() - offset);
+            buffer[offset..offset + len].copy_from_slice(&src[..len]);
+            src = &src[len..];
+            offset = 0;
+            if src.is_empty() {
+                break;
+            }
+        }
+        assert!(src.is_empty(), "[kernel] write out of user buffer");
+    }
 }
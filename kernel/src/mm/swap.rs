@@ -0,0 +1,131 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::config::{PAGE_SIZE, VIRTIO0};
+use crate::drivers::virtio_blk::{VirtIOBlk, SECTOR_SIZE};
+use crate::sync::SpinLock;
+
+use super::address::PhysPageNum;
+
+// 每个交换位置保存一页，占用连续的若干扇区
+const SECTORS_PER_PAGE: usize = (1 << PAGE_SIZE) / SECTOR_SIZE;
+
+// 使用整个 virtio 块设备作为交换区，与页帧的分配方式相同：栈式分配交换位置
+pub struct SwapManager {
+    device: VirtIOBlk,
+    current: usize,
+    end: usize,
+    recycled: Vec<usize>,
+    // 被多个 SwapSlot 共享的位置的引用计数，不在其中的位置只有一个引用
+    ref_counts: BTreeMap<usize, usize>,
+}
+
+impl SwapManager {
+    fn new(device: VirtIOBlk) -> Self {
+        let end = device.capacity() / SECTORS_PER_PAGE;
+        Self {
+            device,
+            current: 0,
+            end,
+            recycled: Vec::new(),
+            ref_counts: BTreeMap::new(),
+        }
+    }
+    fn alloc(&mut self) -> Option<usize> {
+        if let Some(id) = self.recycled.pop() {
+            Some(id)
+        } else if self.current == self.end {
+            None
+        } else {
+            self.current += 1;
+            Some(self.current - 1)
+        }
+    }
+    fn dealloc(&mut self, id: usize) {
+        if id >= self.current || self.recycled.contains(&id) {
+            panic!("dealloc invalid swap slot {}", id);
+        }
+        self.recycled.push(id);
+    }
+}
+
+lazy_static::lazy_static! {
+    // 没有找到块设备时为 None，此时不支持换出
+    static ref SWAP_MANAGER: SpinLock<Option<SwapManager>> = SpinLock::new(None);
+}
+
+// 探测交换设备，需要在内核地址空间（包括 MMIO 区域）启用之后调用
+pub fn init_swap() {
+    match VirtIOBlk::new(VIRTIO0) {
+        Some(device) => {
+            let manager = SwapManager::new(device);
+            println!("[kernel] swap: {} pages on virtio block device", manager.end);
+            *SWAP_MANAGER.lock() = Some(manager);
+        }
+        None => {
+            println!("[kernel] swap: no virtio block device found");
+        }
+    }
+}
+
+// 交换区中的一个位置，与 FrameTracker 一样在被释放时回收
+// fork 时父子进程可以共享同一个位置，最后一个引用被释放时才回收
+pub struct SwapSlot {
+    id: usize,
+}
+
+impl SwapSlot {
+    // 将页帧的内容写入该位置
+    pub fn write(&self, ppn: PhysPageNum) {
+        let mut manager = SWAP_MANAGER.lock();
+        let manager = manager.as_mut().unwrap();
+        manager
+            .device
+            .write(self.id * SECTORS_PER_PAGE, ppn.get_page_array());
+    }
+    // 将该位置的内容读入页帧
+    pub fn read(&self, ppn: PhysPageNum) {
+        let mut manager = SWAP_MANAGER.lock();
+        let manager = manager.as_mut().unwrap();
+        manager
+            .device
+            .read(self.id * SECTORS_PER_PAGE, ppn.get_page_array());
+    }
+    // 是否还被其他 SwapSlot 共享，共享的位置不能被改写
+    pub fn is_shared(&self) -> bool {
+        let manager = SWAP_MANAGER.lock();
+        manager.as_ref().unwrap().ref_counts.contains_key(&self.id)
+    }
+}
+
+impl Clone for SwapSlot {
+    fn clone(&self) -> Self {
+        let mut manager = SWAP_MANAGER.lock();
+        let manager = manager.as_mut().unwrap();
+        *manager.ref_counts.entry(self.id).or_insert(1) += 1;
+        Self { id: self.id }
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        let mut manager = SWAP_MANAGER.lock();
+        let manager = manager.as_mut().unwrap();
+        if let Some(count) = manager.ref_counts.get_mut(&self.id) {
+            *count -= 1;
+            if *count == 1 {
+                manager.ref_counts.remove(&self.id);
+            }
+            return;
+        }
+        manager.dealloc(self.id);
+    }
+}
+
+// 分配一个交换位置，没有交换设备或者交换区已满时返回 None
+pub fn swap_alloc() -> Option<SwapSlot> {
+    SWAP_MANAGER
+        .lock()
+        .as_mut()
+        .and_then(|manager| manager.alloc())
+        .map(|id| SwapSlot { id })
+}
@@ -0,0 +1,27 @@
+use core::arch::asm;
+
+use crate::config::MAX_HARTS;
+use crate::sbi::hart_start;
+
+// 每个 hart 在 entry.asm 中把自己的 hartid 放在 tp 中
+// 用户程序可以修改 tp，因此 tp 作为通用寄存器保存在 TrapContext 中，
+// 进入内核时 __alltraps 从 TrapContext 中取出当前的 hartid 重新放入 tp
+pub fn hart_id() -> usize {
+    let id: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg) id);
+    }
+    id
+}
+
+// 由启动 hart 在完成初始化之后调用，通过 SBI HSM 扩展启动其余的 hart
+// 从 _start_secondary 开始执行；不存在的 hart 启动失败，直接跳过
+pub fn start_secondary_harts() {
+    extern "C" {
+        fn _start_secondary();
+    }
+    let boot_hart = hart_id();
+    for id in (0..MAX_HARTS).filter(|&id| id != boot_hart) {
+        hart_start(id, _start_secondary as usize, 0);
+    }
+}
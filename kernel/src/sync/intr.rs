@@ -0,0 +1,59 @@
+//! Per-hart interrupt masking with nesting depth
+
+use core::cell::UnsafeCell;
+
+use riscv::register::sstatus;
+
+use crate::config::MAX_HARTS;
+use crate::smp::hart_id;
+
+/// Records how many interrupt-free sections the hart is currently in,
+/// and whether interrupts were enabled before the outermost one.
+#[derive(Clone, Copy)]
+struct IntrMaskingInfo {
+    nested_level: usize,
+    sie_before_masking: bool,
+}
+
+struct PerHartIntrMaskingInfo(UnsafeCell<[IntrMaskingInfo; MAX_HARTS]>);
+
+// 每个 hart 只访问自己的那一项，并且只在关中断时修改
+unsafe impl Sync for PerHartIntrMaskingInfo {}
+
+static INTR_MASKING_INFO: PerHartIntrMaskingInfo = PerHartIntrMaskingInfo(UnsafeCell::new(
+    [IntrMaskingInfo {
+        nested_level: 0,
+        sie_before_masking: false,
+    }; MAX_HARTS],
+));
+
+fn current_info() -> &'static mut IntrMaskingInfo {
+    unsafe { &mut (*INTR_MASKING_INFO.0.get())[hart_id()] }
+}
+
+/// Disable interrupts on the current hart and enter an interrupt-free
+/// section. Sections can be nested; every call must be paired with `pop_off`.
+pub fn push_off() {
+    let sie = sstatus::read().sie();
+    unsafe {
+        sstatus::clear_sie();
+    }
+    let info = current_info();
+    if info.nested_level == 0 {
+        info.sie_before_masking = sie;
+    }
+    info.nested_level += 1;
+}
+
+/// Leave an interrupt-free section. Interrupts are re-enabled when the
+/// outermost section is left, if they were enabled before it.
+pub fn pop_off() {
+    let info = current_info();
+    assert!(info.nested_level > 0, "pop_off without push_off");
+    info.nested_level -= 1;
+    if info.nested_level == 0 && info.sie_before_masking {
+        unsafe {
+            sstatus::set_sie();
+        }
+    }
+}
@@ -1,5 +1,8 @@
 //! Synchronization and interior mutability primitives
 
+mod intr;
+mod spin;
 mod up;
 
-pub use up::UPSafeCell;
+pub use spin::{SpinLock, SpinLockGuard};
+pub use up::{UPIntrFreeCell, UPIntrRefMut};
@@ -1,31 +1,63 @@
 //! Uniprocessor interior mutability primitives
 
 use core::cell::{RefCell, RefMut};
+use core::ops::{Deref, DerefMut};
+
+use super::intr::{pop_off, push_off};
 
 /// Wrap a static data structure inside it so that we are
 /// able to access it without any `unsafe`.
 ///
-/// We should only use it in uniprocessor.
+/// We should only use it for data that is accessed by a single hart,
+/// such as the per-hart `Processor`.
+///
+/// Interrupts on the current hart are disabled while the data is
+/// borrowed, so a trap handler can not observe it half-modified.
 ///
 /// In order to get mutable reference of inner data, call
 /// `exclusive_access`.
-pub struct UPSafeCell<T> {
+pub struct UPIntrFreeCell<T> {
     /// inner data
     inner: RefCell<T>,
 }
 
-unsafe impl<T> Sync for UPSafeCell<T> {}
+unsafe impl<T> Sync for UPIntrFreeCell<T> {}
+
+pub struct UPIntrRefMut<'a, T>(Option<RefMut<'a, T>>);
 
-impl<T> UPSafeCell<T> {
-    /// User is responsible to guarantee that inner struct is only used in
-    /// uniprocessor.
+impl<T> UPIntrFreeCell<T> {
+    /// User is responsible to guarantee that inner struct is only used by
+    /// one hart.
     pub unsafe fn new(value: T) -> Self {
         Self {
             inner: RefCell::new(value),
         }
     }
-    /// Exclusive access inner data in UPSafeCell. Panic if the data has been borrowed.
-    pub fn exclusive_access(&self) -> RefMut<'_, T> {
-        self.inner.borrow_mut()
+    /// Disable interrupts and exclusive access inner data. Panic if the
+    /// data has been borrowed.
+    pub fn exclusive_access(&self) -> UPIntrRefMut<'_, T> {
+        push_off();
+        UPIntrRefMut(Some(self.inner.borrow_mut()))
+    }
+}
+
+impl<T> Drop for UPIntrRefMut<'_, T> {
+    fn drop(&mut self) {
+        // 先释放借用，再恢复中断
+        self.0 = None;
+        pop_off();
+    }
+}
+
+impl<T> Deref for UPIntrRefMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.0.as_ref().unwrap().deref()
+    }
+}
+
+impl<T> DerefMut for UPIntrRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.0.as_mut().unwrap().deref_mut()
     }
 }
@@ -1,12 +1,18 @@
-use crate::task::current_tasktoken;
-use crate::mm::page_table::translated_byte_buffer;
+use crate::mm::{memory_set::MapPermission, page_table::translated_byte_buffer};
+use crate::sbi::console_getchar;
+use crate::task::{current_tasktoken, suspended_current_and_run_next};
+const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     match fd {
         FD_STDOUT => {
             // 需要更正获取数据的方式，因为数据在应用的地址空间，内核看不到（buf）的指针地址是应用地址空间的
-            let buffers = translated_byte_buffer(current_tasktoken(), buf, len);
+            let buffers =
+                match translated_byte_buffer(current_tasktoken(), buf, len, MapPermission::R) {
+                    Some(buffers) => buffers,
+                    None => return -1,
+                };
             // let slice = unsafe { core::slice::from_raw_parts(buf, len) };
             for buffer in buffers {
                 print!("{}", core::str::from_utf8(buffer).unwrap());
@@ -14,7 +20,60 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
             len as isize
         }
         _ => {
-            panic!("Unsupported fd in sys_write!");
+            println!("[kernel] Unsupported fd {} in sys_write!", fd);
+            -1
+        }
+    }
+}
+
+// 尝试从 SBI 读取一个字符，没有输入时返回 None
+fn try_getchar() -> Option<u8> {
+    let c = console_getchar();
+    if c == 0 || c as isize == -1 {
+        None
+    } else {
+        Some(c as u8)
+    }
+}
+
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+    match fd {
+        FD_STDIN => {
+            if len == 0 {
+                return 0;
+            }
+            // buf 在应用地址空间中，通过用户页表找到对应的物理页再写入
+            // 先检查 buf 再读取字符，否则 buf 非法时读到的字符会丢失
+            let buffers =
+                match translated_byte_buffer(current_tasktoken(), buf, len, MapPermission::W) {
+                    Some(buffers) => buffers,
+                    None => return -1,
+                };
+            // 至少读到一个字符才返回，没有输入时让出 CPU 而不是忙等
+            let first = loop {
+                if let Some(c) = try_getchar() {
+                    break c;
+                }
+                suspended_current_and_run_next();
+            };
+            let mut next = Some(first);
+            let mut read = 0;
+            'copy: for buffer in buffers {
+                for byte in buffer.iter_mut() {
+                    match next {
+                        Some(c) => *byte = c,
+                        None => break 'copy,
+                    }
+                    read += 1;
+                    // 后续字符有多少读多少，不再等待
+                    next = if read < len { try_getchar() } else { None };
+                }
+            }
+            read as isize
+        }
+        _ => {
+            println!("[kernel] Unsupported fd {} in sys_read!", fd);
+            -1
         }
     }
 }
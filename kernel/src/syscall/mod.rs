@@ -1,17 +1,44 @@
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_SLEEP: usize = 101;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_SET_PRIORITY: usize = 140;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_SBRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_FORK: usize = 220;
+const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_SCHED_SETATTR: usize = 274;
+const SYSCALL_SPAWN: usize = 400;
+const SYSCALL_TASK_INFO: usize = 410;
 
 mod fs;
 mod process;
 
+use crate::task::{count_current_syscall, TaskInfo};
+
 pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+    count_current_syscall(syscall_id);
     match syscall_id {
+        SYSCALL_READ => fs::sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => fs::sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_EXIT => process::sys_exit(args[0] as i32),
+        SYSCALL_SLEEP => process::sys_sleep(args[0]),
         SYSCALL_YIELD=> process::sys_yield(),
+        SYSCALL_SET_PRIORITY => process::sys_set_priority(args[0] as isize),
         SYSCALL_GET_TIME => process::sys_get_time(),
+        SYSCALL_SBRK => process::sys_sbrk(args[0] as i32),
+        SYSCALL_MUNMAP => process::sys_munmap(args[0], args[1]),
+        SYSCALL_FORK => process::sys_fork(),
+        SYSCALL_EXEC => process::sys_exec(args[0] as *const u8),
+        SYSCALL_MMAP => process::sys_mmap(args[0], args[1], args[2]),
+        SYSCALL_WAITPID => process::sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_SCHED_SETATTR => process::sys_sched_setattr(args[0], args[1]),
+        SYSCALL_SPAWN => process::sys_spawn(args[0] as *const u8),
+        SYSCALL_TASK_INFO => process::sys_task_info(args[0] as *mut TaskInfo),
         _ => panic!("unknown syscall_id: {}", syscall_id),
     }
 }
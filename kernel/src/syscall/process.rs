@@ -1,14 +1,33 @@
-use crate::{task::{exit_current_and_run_next, suspended_current_and_run_next, current_taskinfo}, timer::get_time_ms};
+use crate::{
+    config::PAGE_SIZE,
+    loader::get_app_data_by_name,
+    mm::{
+        address::VirtAddr,
+        memory_set::MapPermission,
+        page_table::{translated_refmut, translated_str},
+    },
+    task::{
+        current_tasktoken, exec_current, exit_current_and_run_next, fork_current, mmap_current,
+        munmap_current, sbrk_current, set_current_priority, set_current_realtime, sleep_current,
+        spawn_current, suspended_current_and_run_next, waitpid_current, write_current_taskinfo,
+        TaskInfo,
+    },
+    timer::get_time_ms,
+};
 
 pub fn sys_exit(exit_code: i32) -> ! {
     println!("[kernel] Application exited with code {}", exit_code);
     // 不再是运行下一个应用，而是退出继续运行，与下面的暂停运行（sys_yield）相对比
-    exit_current_and_run_next();
+    exit_current_and_run_next(exit_code);
     panic!("[kernel] [task_exit]Should not reach here");
 }
 
-pub fn get_taskinfo() {
-    current_taskinfo();
+pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
+    if write_current_taskinfo(ti) {
+        0
+    } else {
+        -1
+    }
 }
 
 pub fn sys_yield() -> isize {
@@ -16,6 +35,136 @@ pub fn sys_yield() -> isize {
     0
 }
 
-pub fn sys_get_time() -> isize{
+// 阻塞当前任务 ms 毫秒，由定时器到期时唤醒
+pub fn sys_sleep(ms: usize) -> isize {
+    sleep_current(ms);
+    0
+}
+
+// 优先级至少为 2，保证 stride 不超过 BIG_STRIDE / 2
+pub fn sys_set_priority(priority: isize) -> isize {
+    if priority < 2 {
+        return -1;
+    }
+    set_current_priority(priority as usize);
+    priority
+}
+
+// 成为周期为 period 毫秒、每个周期最多运行 budget 毫秒的实时任务
+pub fn sys_sched_setattr(period: usize, budget: usize) -> isize {
+    if budget == 0 || budget > period || !set_current_realtime(period, budget) {
+        return -1;
+    }
+    0
+}
+
+// 将堆的末尾移动 size 字节（可以为负），返回原来的 program break，失败时返回 -1
+pub fn sys_sbrk(size: i32) -> isize {
+    match sbrk_current(size as isize) {
+        Some(old_brk) => old_brk as isize,
+        None => -1,
+    }
+}
+
+// 用户地址空间只使用 SV39 的低 256GiB
+const USER_SPACE_END: usize = 1 << 38;
+
+// 检查 [start, start + len) 是页对齐的非空用户地址范围，返回其 [起始页号, 结束页号)
+fn user_page_range(start: usize, len: usize) -> Option<(VirtAddr, VirtAddr)> {
+    let end = start.checked_add(len)?;
+    if start % (1 << PAGE_SIZE) != 0 || len == 0 || end > USER_SPACE_END {
+        return None;
+    }
+    Some((start.into(), end.into()))
+}
+
+// 在 start 处映射 len 字节的匿名内存，len 向上取整到页
+// prot 的第 0、1、2 位分别表示可读、可写、可执行，其余位必须为 0
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
+    if prot & !0x7 != 0 || prot & 0x7 == 0 {
+        return -1;
+    }
+    let (start, end) = match user_page_range(start, len) {
+        Some(range) => range,
+        None => return -1,
+    };
+    let mut permission = MapPermission::from_bits((prot as u8) << 1).unwrap() | MapPermission::U;
+    // RISC-V 保留了可写但不可读的页表项编码，与 Linux 相同，可写的页同时可读
+    if permission.contains(MapPermission::W) {
+        permission |= MapPermission::R;
+    }
+    if mmap_current(start.floor(), end.ceil(), permission) {
+        0
+    } else {
+        -1
+    }
+}
+
+// 解除 start 开始 len 字节的映射，范围内的每一页都必须已经映射
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    let (start, end) = match user_page_range(start, len) {
+        Some(range) => range,
+        None => return -1,
+    };
+    if munmap_current(start.floor(), end.ceil()) {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn sys_get_time() -> isize {
     get_time_ms() as isize
-}
\ No newline at end of file
+}
+
+pub fn sys_fork() -> isize {
+    // 父进程返回子进程的 pid，子进程的返回值已在其 trap context 中置 0
+    fork_current() as isize
+}
+
+pub fn sys_exec(path: *const u8) -> isize {
+    // path 位于用户地址空间，需要通过用户页表读出
+    let path = match translated_str(current_tasktoken(), path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    if let Some(data) = get_app_data_by_name(path.as_str()) {
+        exec_current(data);
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+    // 先检查 exit_code_ptr，避免回收了子进程之后才发现无法写入退出码
+    let exit_code_ref = if exit_code_ptr.is_null() {
+        None
+    } else {
+        match translated_refmut(current_tasktoken(), exit_code_ptr) {
+            Some(exit_code_ref) => Some(exit_code_ref),
+            None => return -1,
+        }
+    };
+    match waitpid_current(pid) {
+        Ok((found_pid, exit_code)) => {
+            if let Some(exit_code_ref) = exit_code_ref {
+                *exit_code_ref = exit_code;
+            }
+            found_pid as isize
+        }
+        Err(err) => err,
+    }
+}
+
+pub fn sys_spawn(path: *const u8) -> isize {
+    let path = match translated_str(current_tasktoken(), path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    if let Some(data) = get_app_data_by_name(path.as_str()) {
+        spawn_current(data) as isize
+    } else {
+        -1
+    }
+}
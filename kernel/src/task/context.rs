@@ -1,3 +1,4 @@
+use super::kernel_thread_entry;
 use crate::trap::trap_return;
 
 #[repr(C)]
@@ -33,4 +34,12 @@ impl TaskContext {
             s: [0; 12],
         }
     }
+    // 内核线程第一次被调度时从 kernel_thread_entry 开始执行
+    pub fn goto_kernel_thread_entry(kstack_ptr: usize) -> Self {
+        Self {
+            ra: kernel_thread_entry as usize,
+            sp: kstack_ptr,
+            s: [0; 12],
+        }
+    }
 }
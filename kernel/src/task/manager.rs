@@ -0,0 +1,122 @@
+use alloc::sync::Arc;
+
+use super::scheduler::{EdfScheduler, Scheduler, TaskScheduler};
+use super::task::TaskControlBlock;
+use crate::sync::SpinLock;
+
+// 就绪的任务交给调度器管理，正在运行的任务由 Processor 持有
+// 设置了周期与预算的实时任务由 rt_scheduler 管理，优先于普通任务
+pub struct TaskManager {
+    scheduler: TaskScheduler,
+    rt_scheduler: EdfScheduler,
+    // 尚未退出的任务数，包括不在就绪队列中的（正在运行或被阻塞的）任务
+    alive: usize,
+    // 所有任务退出后打印的统计信息
+    created: usize,
+    failed: usize,
+}
+
+impl TaskManager {
+    pub fn new() -> Self {
+        Self {
+            scheduler: TaskScheduler::new(),
+            rt_scheduler: EdfScheduler::new(),
+            alive: 0,
+            created: 0,
+            failed: 0,
+        }
+    }
+    pub fn on_task_created(&mut self) {
+        self.alive += 1;
+        self.created += 1;
+    }
+    pub fn on_task_exited(&mut self, exit_code: i32) {
+        self.alive -= 1;
+        if exit_code != 0 {
+            self.failed += 1;
+        }
+    }
+    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+        if is_realtime(&task) {
+            self.rt_scheduler.add(task);
+        } else {
+            self.scheduler.add(task);
+        }
+    }
+    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.rt_scheduler.fetch().or_else(|| self.scheduler.fetch())
+    }
+    pub fn tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        if is_realtime(current) {
+            self.scheduler.on_other_tick();
+            return self.rt_scheduler.on_tick(current);
+        }
+        // 普通任务在有实时任务就绪时总是被抢占
+        let need_switch = self.scheduler.on_tick(current);
+        need_switch || self.rt_scheduler.has_ready()
+    }
+    pub fn block(&mut self, task: &Arc<TaskControlBlock>) {
+        if is_realtime(task) {
+            self.rt_scheduler.on_block(task);
+        } else {
+            self.scheduler.on_block(task);
+        }
+    }
+    pub fn exit(&mut self, task: &Arc<TaskControlBlock>) {
+        if is_realtime(task) {
+            self.rt_scheduler.on_exit(task);
+        } else {
+            self.scheduler.on_exit(task);
+        }
+    }
+}
+
+fn is_realtime(task: &Arc<TaskControlBlock>) -> bool {
+    task.inner_exclusive_access().rt.is_some()
+}
+
+lazy_static::lazy_static! {
+    /// Global variable: TASK_MANAGER
+    pub static ref TASK_MANAGER: SpinLock<TaskManager> = SpinLock::new(TaskManager::new());
+}
+
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.lock().add(task);
+}
+
+pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.lock().fetch()
+}
+
+pub fn scheduler_tick(current: &Arc<TaskControlBlock>) -> bool {
+    TASK_MANAGER.lock().tick(current)
+}
+
+pub fn scheduler_block(task: &Arc<TaskControlBlock>) {
+    TASK_MANAGER.lock().block(task);
+}
+
+pub fn scheduler_exit(task: &Arc<TaskControlBlock>) {
+    TASK_MANAGER.lock().exit(task);
+}
+
+pub fn task_created() {
+    TASK_MANAGER.lock().on_task_created();
+}
+
+pub fn task_exited(exit_code: i32) {
+    TASK_MANAGER.lock().on_task_exited(exit_code);
+}
+
+pub fn alive_task_num() -> usize {
+    TASK_MANAGER.lock().alive
+}
+
+// 所有任务退出之后调用，打印统计信息
+pub fn print_summary() {
+    let manager = TASK_MANAGER.lock();
+    println!(
+        "[kernel] All {} tasks exited, {} of them with non-zero exit code.",
+        manager.created, manager.failed
+    );
+}
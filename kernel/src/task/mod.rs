@@ -1,60 +1,294 @@
-use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::mem::size_of;
+use core::ptr::addr_of_mut;
+use riscv::register::sstatus;
 
 use crate::{
-    loader::{get_num_app, get_app_data},
-    sync::UPSafeCell,
-    task::task::TaskControlBlock, trap::context::TrapContext,
+    config::MAX_SYSCALL_NUM,
+    loader::{get_app_data, get_num_app},
+    mm::{address::VirtPageNum, memory_set::MapPermission, page_table::UserBuffer},
+    task::{context::TaskContext, task::TaskControlBlock},
+    timer::{add_timer, get_time, get_time_ms, ms_to_ticks},
 };
 
 mod context;
+mod manager;
+mod pid;
+mod processor;
+mod scheduler;
 mod switch;
 mod task;
 
-lazy_static::lazy_static! {
-    /// Global variable: TASK_MANAGER
-    pub static ref TASK_MANAGER: task::TaskManger = {
-        println!("init TASK_MANAGER");
-        let num_app = get_num_app();
-        println!("num_app = {}", num_app);
+pub use manager::add_task;
+pub use processor::{current_task, current_tasktoken, current_trap_cx, run_tasks};
+pub use task::{TaskInfo, TaskStatus};
 
-        let mut tasks = Vec::<TaskControlBlock>::new();
-        for i in 0..num_app {
-            tasks.push(TaskControlBlock::new(get_app_data(i), i));
-        }
+use manager::{scheduler_block, scheduler_exit, scheduler_tick, task_created, task_exited};
+use processor::schedule;
+use scheduler::RtEntity;
 
-        task::TaskManger {
-            num_app,
-            inner: unsafe {
-                UPSafeCell::new(task::TaskMangerInner{
-                    tasks,
-                    current_task: 0,
-                })
-            },
-        }
-    };
+// 将所有内嵌的应用加入就绪队列
+pub fn add_apps() {
+    let num_app = get_num_app();
+    println!("num_app = {}", num_app);
+    for i in 0..num_app {
+        task_created();
+        add_task(Arc::new(TaskControlBlock::new(get_app_data(i))));
+    }
 }
+
 pub fn suspended_current_and_run_next() {
-    TASK_MANAGER.mark_current_suspended();
-    TASK_MANAGER.run_next_task();
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.status = TaskStatus::Ready;
+    drop(task_inner);
+    // 交还给调度器
+    add_task(task);
+    schedule(task_cx_ptr);
+}
+
+// 当前任务进入阻塞状态，不再加入就绪队列，直到被 wakeup_task 唤醒
+pub fn block_current_and_run_next() {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.status = TaskStatus::Blocked;
+    drop(task_inner);
+    scheduler_block(&task);
+    drop(task);
+    schedule(task_cx_ptr);
+}
+
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    task.inner_exclusive_access().status = TaskStatus::Ready;
+    add_task(task);
+}
+
+// 时钟中断时调用，由调度器决定是否切换到其他任务
+pub fn tick_current_and_run_next() {
+    let task = current_task().unwrap();
+    let need_switch = scheduler_tick(&task);
+    drop(task);
+    if need_switch {
+        suspended_current_and_run_next();
+    }
+}
+
+pub fn exit_current_and_run_next(exit_code: i32) {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.status = TaskStatus::Zombie;
+    inner.exit_code = exit_code;
+    // 子进程不再有父进程等待，已经退出的子进程随 children 一起释放
+    for child in inner.children.iter() {
+        child.inner_exclusive_access().parent = None;
+    }
+    inner.children.clear();
+    // 用户地址空间中的页帧可以立即释放，内核栈留到 TCB 被释放时回收
+    if let Some(memory_set) = inner.memory_set.as_mut() {
+        memory_set.recycle_data_pages();
+    }
+    drop(inner);
+    scheduler_exit(&task);
+    let kernel_thread = task.kernel_thread;
+    drop(task);
+    if !kernel_thread {
+        task_exited(exit_code);
+    }
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
+}
+
+// 当前任务睡眠 ms 毫秒，期间不占用 CPU
+pub fn sleep_current(ms: usize) {
+    let task = current_task().unwrap();
+    add_timer(
+        get_time() + ms_to_ticks(ms),
+        Box::new(move || wakeup_task(task)),
+    );
+    block_current_and_run_next();
+}
+
+// 创建一个执行 f 的内核线程并加入就绪队列，返回其 pid
+// 内核线程不计入 alive_task_num，所有用户任务退出之后不会等待它们
+// 目前只有启用 kernel_test 时的自测会创建内核线程
+#[allow(dead_code)]
+pub fn spawn_kernel_thread<F: FnOnce() + Send + 'static>(f: F) -> usize {
+    let thread = Arc::new(TaskControlBlock::new_kernel_thread(Box::new(f)));
+    let pid = thread.getpid();
+    add_task(thread);
+    pid
+}
+
+// 内核线程的入口：打开中断以便被时钟中断抢占，执行完函数之后退出
+pub fn kernel_thread_entry() -> ! {
+    let f = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .kernel_thread_fn
+        .take()
+        .unwrap();
+    unsafe {
+        sstatus::set_sie();
+    }
+    f();
+    exit_current_and_run_next(0);
+    unreachable!("kernel thread should not be scheduled after exit");
+}
+
+// 内核线程的简单测试：与其他任务交替运行几次之后退出
+#[cfg(feature = "kernel_test")]
+pub fn kernel_thread_test() {
+    spawn_kernel_thread(|| {
+        for _ in 0..3 {
+            suspended_current_and_run_next();
+        }
+        println!("[kernel] kernel thread test passed!");
+    });
 }
 
-pub fn exit_current_and_run_next() {
-    TASK_MANAGER.mark_current_exited();
-    TASK_MANAGER.run_next_task();
+// 复制当前任务，返回子任务的 pid
+pub fn fork_current() -> usize {
+    let current = current_task().unwrap();
+    let child = current.fork();
+    let pid = child.getpid();
+    // 子进程中 fork 的返回值为 0
+    child.inner_exclusive_access().get_trap_cx().x[10] = 0;
+    // 先计数再加入就绪队列，否则子进程可能在其他 hart 上运行并退出，
+    // 使存活任务数提前降到 0
+    task_created();
+    add_task(child);
+    pid
 }
 
-pub fn start_run_first_task() {
-    TASK_MANAGER.run_first_task();
+// 以当前任务为父进程，从 ELF 创建一个新任务，返回其 pid
+pub fn spawn_current(elf_data: &'static [u8]) -> usize {
+    let current = current_task().unwrap();
+    let child = current.spawn(elf_data);
+    let pid = child.getpid();
+    task_created();
+    add_task(child);
+    pid
 }
 
-pub fn current_taskinfo() -> usize {
-    TASK_MANAGER.get_current_task()
+// 等待当前任务的子进程退出：pid 为 -1 时等待任意子进程
+// 成功时返回子进程的 pid 与退出码；不存在对应子进程返回 -1，子进程仍在运行返回 -2
+pub fn waitpid_current(pid: isize) -> Result<(usize, i32), isize> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let matched = |child: &Arc<TaskControlBlock>| pid == -1 || pid as usize == child.getpid();
+    if !inner.children.iter().any(matched) {
+        return Err(-1);
+    }
+    let idx = inner
+        .children
+        .iter()
+        .position(|child| matched(child) && child.inner_exclusive_access().is_zombie())
+        .ok_or(-2isize)?;
+    let child = inner.children.remove(idx);
+    let found_pid = child.getpid();
+    let exit_code = child.inner_exclusive_access().exit_code;
+    // child 在这里被释放，其内核栈、pid 以及剩余的页表随之回收
+    Ok((found_pid, exit_code))
 }
 
-pub fn current_tasktoken() -> usize {
-    TASK_MANAGER.get_current_token()
+pub fn exec_current(elf_data: &'static [u8]) {
+    current_task().unwrap().exec(elf_data);
 }
 
-pub fn current_trap_cx() -> &'static mut TrapContext {
-    TASK_MANAGER.get_current_trap_cx()
-}
\ No newline at end of file
+pub fn sbrk_current(size: isize) -> Option<usize> {
+    current_task().unwrap().change_program_brk(size)
+}
+
+// 处理当前任务在 vpn 处的缺页，access 为访问需要的权限，无法处理时返回 false
+pub fn handle_current_page_fault(vpn: VirtPageNum, access: MapPermission) -> bool {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_memory_set()
+        .handle_page_fault(vpn, access)
+}
+
+// 固定当前任务在 [start, end) 中的页，直到调用 unpin_current_pages 之前都不会被换出
+pub fn pin_current_pages(start: VirtPageNum, end: VirtPageNum) {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_memory_set()
+        .pin(start, end);
+}
+
+pub fn unpin_current_pages() {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_memory_set()
+        .unpin();
+}
+
+pub fn mmap_current(start: VirtPageNum, end: VirtPageNum, permission: MapPermission) -> bool {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_memory_set()
+        .mmap(start, end, permission)
+}
+
+pub fn munmap_current(start: VirtPageNum, end: VirtPageNum) -> bool {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_memory_set()
+        .munmap(start, end)
+}
+
+pub fn set_current_priority(priority: usize) {
+    current_task().unwrap().set_priority(priority);
+}
+
+// 将当前任务设置为周期为 period_ms、每个周期运行 budget_ms 的实时任务
+// 从下一次被调度开始由 EDF 调度，参数过大时返回 false
+pub fn set_current_realtime(period_ms: usize, budget_ms: usize) -> bool {
+    match RtEntity::new(period_ms, budget_ms) {
+        Some(rt) => {
+            current_task().unwrap().inner_exclusive_access().rt = Some(rt);
+            true
+        }
+        None => false,
+    }
+}
+
+// 将当前任务的信息写入用户地址空间中的 ti，ti 无效时返回 false
+// TaskInfo 约有 2KiB，不在内核栈上构造，而是从 TCB 中逐个字段直接写入
+pub fn write_current_taskinfo(ti: *mut TaskInfo) -> bool {
+    let token = current_tasktoken();
+    let mut buffer = match UserBuffer::new(token, ti as *const u8, size_of::<TaskInfo>()) {
+        Some(buffer) => buffer,
+        None => return false,
+    };
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let time = inner
+        .start_time
+        .map_or(0, |start_time| get_time_ms() - start_time);
+    let deadline_misses = inner.rt.as_ref().map_or(0, |rt| rt.deadline_misses);
+    unsafe {
+        buffer.write(addr_of_mut!((*ti).status), &inner.status);
+        buffer.write(addr_of_mut!((*ti).syscall_times), &inner.syscall_times);
+        buffer.write(addr_of_mut!((*ti).time), &time);
+        buffer.write(addr_of_mut!((*ti).deadline_misses), &deadline_misses);
+    }
+    true
+}
+
+// 在 syscall 分发之前调用，记录当前任务的系统调用次数
+pub fn count_current_syscall(syscall_id: usize) {
+    if syscall_id < MAX_SYSCALL_NUM {
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .syscall_times[syscall_id] += 1;
+    }
+}
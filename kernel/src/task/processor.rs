@@ -0,0 +1,131 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+
+use super::context::TaskContext;
+use super::manager::{alive_task_num, fetch_task, print_summary};
+use super::switch::__switch;
+use super::task::{TaskControlBlock, TaskStatus};
+use crate::config::MAX_HARTS;
+use crate::sbi::shutdown;
+use crate::smp::hart_id;
+use crate::sync::{UPIntrFreeCell, UPIntrRefMut};
+use crate::timer::get_time_ms;
+use crate::trap::context::TrapContext;
+use riscv::register::sstatus;
+
+// 描述一个 hart 的执行状态：正在运行的任务，以及用于调度的 idle 控制流
+pub struct Processor {
+    current: Option<Arc<TaskControlBlock>>,
+    idle_task_cx: TaskContext,
+}
+
+impl Processor {
+    pub fn new() -> Self {
+        Self {
+            current: None,
+            idle_task_cx: TaskContext::zero_init(),
+        }
+    }
+    fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
+        &mut self.idle_task_cx as *mut _
+    }
+    pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
+        self.current.as_ref().map(Arc::clone)
+    }
+}
+
+lazy_static::lazy_static! {
+    // 每个 hart 一个，只会被对应的 hart 访问
+    static ref PROCESSORS: Vec<UPIntrFreeCell<Processor>> = (0..MAX_HARTS)
+        .map(|_| unsafe { UPIntrFreeCell::new(Processor::new()) })
+        .collect();
+}
+
+fn current_processor() -> UPIntrRefMut<'static, Processor> {
+    PROCESSORS[hart_id()].exclusive_access()
+}
+
+// idle 控制流：不断从 TaskManager 中取出任务并切换过去
+// 所有任务都退出后关机
+pub fn run_tasks() -> ! {
+    loop {
+        let mut processor = current_processor();
+        // 上一个任务已经切换出去，释放对它的引用
+        // 退出且没有父进程的任务在这里才真正被释放，此时已经不在它的内核栈上
+        if let Some(prev) = processor.current.take() {
+            prev.inner_exclusive_access().on_cpu = false;
+        }
+        if let Some(task) = fetch_task() {
+            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+            // 任务可能在另一个 hart 切换出去之前就被放回了就绪队列，等它保存完 task_cx
+            let mut task_inner = loop {
+                let task_inner = task.inner_exclusive_access();
+                if !task_inner.on_cpu {
+                    break task_inner;
+                }
+                drop(task_inner);
+                spin_loop();
+            };
+            let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
+            task_inner.status = TaskStatus::Running;
+            task_inner.on_cpu = true;
+            if task_inner.start_time.is_none() {
+                task_inner.start_time = Some(get_time_ms());
+            }
+            drop(task_inner);
+            processor.current = Some(task);
+            drop(processor);
+            unsafe {
+                // 内核栈可能在其他 hart 上被回收后重新映射到了别的页帧，清空本 hart 的 TLB
+                riscv::asm::sfence_vma_all();
+                __switch(idle_task_cx_ptr, next_task_cx_ptr);
+            }
+        } else if alive_task_num() == 0 {
+            drop(processor);
+            print_summary();
+            shutdown();
+        } else {
+            drop(processor);
+            // 还有任务存在但都不可运行，打开中断并等待，直到中断使某个任务重新就绪
+            unsafe {
+                sstatus::set_sie();
+                riscv::asm::wfi();
+                sstatus::clear_sie();
+            }
+        }
+    }
+}
+
+pub fn current_task() -> Option<Arc<TaskControlBlock>> {
+    current_processor().current()
+}
+
+pub fn current_tasktoken() -> usize {
+    let task = current_task().unwrap();
+    let token = task.inner_exclusive_access().get_user_token();
+    token
+}
+
+pub fn current_trap_cx() -> &'static mut TrapContext {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_trap_cx()
+}
+
+// 当前任务让出 CPU，切换回 idle 控制流，由 run_tasks 选出下一个任务
+pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
+    let mut processor = current_processor();
+    let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+    drop(processor);
+    // 内核线程运行时打开了中断，idle 控制流与其他任务需要在关中断的状态下运行
+    let sie = sstatus::read().sie();
+    unsafe {
+        sstatus::clear_sie();
+        __switch(switched_task_cx_ptr, idle_task_cx_ptr);
+        if sie {
+            sstatus::set_sie();
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+use super::{Scheduler, DEFAULT_PRIORITY};
+use crate::config::CLOCK_FREQ;
+use crate::task::task::TaskControlBlock;
+use crate::timer::get_time;
+
+// 默认优先级对应的权重，vruntime = 实际运行时间 * NICE_0_WEIGHT / weight
+const NICE_0_WEIGHT: u64 = DEFAULT_PRIORITY as u64;
+// 最小调度粒度（1ms），当前任务的 vruntime 超出最小者这么多时才抢占
+const SCHED_GRANULARITY: u64 = (CLOCK_FREQ / 1000) as u64;
+
+#[derive(Clone, Copy)]
+pub struct SchedEntity {
+    // 权重与优先级成正比
+    weight: u64,
+    vruntime: u64,
+    // 开始运行（或者上一次记账）的时间，不在运行时为 None
+    exec_start: Option<usize>,
+}
+
+impl SchedEntity {
+    pub fn new() -> Self {
+        Self {
+            weight: NICE_0_WEIGHT,
+            vruntime: 0,
+            exec_start: None,
+        }
+    }
+    pub fn set_priority(&mut self, priority: usize) {
+        self.weight = priority as u64;
+    }
+    pub fn for_child(&self) -> Self {
+        Self {
+            weight: self.weight,
+            vruntime: self.vruntime,
+            exec_start: None,
+        }
+    }
+    // 把上一次记账以来的运行时间按权重折算到 vruntime 中
+    fn update_vruntime(&mut self, now: usize) {
+        if let Some(start) = self.exec_start {
+            self.vruntime += (now - start) as u64 * NICE_0_WEIGHT / self.weight;
+            self.exec_start = Some(now);
+        }
+    }
+}
+
+// 每次选出 vruntime 最小的任务
+pub struct CfsScheduler {
+    // 以 (vruntime, 序号) 为键，vruntime 相同时先加入的先运行
+    timeline: BTreeMap<(u64, usize), Arc<TaskControlBlock>>,
+    // 单调不减，新加入或者长时间没有运行的任务从这里开始，避免其长时间独占 CPU
+    min_vruntime: u64,
+    seq: usize,
+}
+
+impl Scheduler for CfsScheduler {
+    fn new() -> Self {
+        Self {
+            timeline: BTreeMap::new(),
+            min_vruntime: 0,
+            seq: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut inner = task.inner_exclusive_access();
+        let entity = &mut inner.sched;
+        entity.update_vruntime(get_time());
+        entity.exec_start = None;
+        entity.vruntime = entity.vruntime.max(self.min_vruntime);
+        let key = (entity.vruntime, self.seq);
+        drop(inner);
+        self.seq += 1;
+        self.timeline.insert(key, task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let key = *self.timeline.keys().next()?;
+        let task = self.timeline.remove(&key)?;
+        self.min_vruntime = self.min_vruntime.max(key.0);
+        task.inner_exclusive_access().sched.exec_start = Some(get_time());
+        Some(task)
+    }
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        let mut inner = current.inner_exclusive_access();
+        inner.sched.update_vruntime(get_time());
+        let vruntime = inner.sched.vruntime;
+        drop(inner);
+        self.timeline
+            .keys()
+            .next()
+            .map_or(false, |&(min, _)| min + SCHED_GRANULARITY < vruntime)
+    }
+    fn on_block(&mut self, task: &Arc<TaskControlBlock>) {
+        // 阻塞之前的运行时间计入 vruntime，阻塞期间不再记账
+        let mut inner = task.inner_exclusive_access();
+        inner.sched.update_vruntime(get_time());
+        inner.sched.exec_start = None;
+    }
+}
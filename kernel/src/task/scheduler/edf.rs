@@ -0,0 +1,129 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::Scheduler;
+use crate::config::CLOCK_FREQ;
+use crate::task::manager::add_task;
+use crate::task::task::TaskControlBlock;
+use crate::timer::{add_timer, get_time, TIME_SLICE_COUNT};
+
+// 实时任务的周期与预算，以及当前周期的状态，通过 sys_sched_setattr 设置
+pub struct RtEntity {
+    // 周期长度（mtime 计数）
+    period: usize,
+    // 每个周期内可以运行的时钟中断数
+    budget: usize,
+    remaining: usize,
+    // 当前周期的截止时间，同时也是下一个周期的开始
+    deadline: usize,
+    // 本周期的预算已经用完，等到下一个周期开始才能再次运行
+    throttled: bool,
+    pub deadline_misses: usize,
+}
+
+impl RtEntity {
+    // 调用者保证 0 < budget_ms <= period_ms，参数来自用户，计算溢出时返回 None
+    pub fn new(period_ms: usize, budget_ms: usize) -> Option<Self> {
+        let period = period_ms.checked_mul(CLOCK_FREQ / 1000)?;
+        // 预算按时钟中断向上取整
+        let budget = budget_ms.checked_mul(TIME_SLICE_COUNT)?.checked_add(999)? / 1000;
+        Some(Self {
+            period,
+            budget,
+            remaining: budget,
+            deadline: get_time().checked_add(period)?,
+            throttled: false,
+            deadline_misses: 0,
+        })
+    }
+    // 跳过所有已经结束的周期，count_miss 表示这段时间任务一直可以运行
+    // 此时到了截止时间还没有用完预算，说明错过了截止时间
+    fn catch_up(&mut self, now: usize, count_miss: bool) {
+        while self.deadline <= now {
+            if count_miss && self.remaining > 0 {
+                self.deadline_misses += 1;
+            }
+            self.deadline += self.period;
+            self.remaining = self.budget;
+        }
+    }
+}
+
+// 截止时间最早的实时任务优先运行，整个实时调度类优先于普通任务
+pub struct EdfScheduler {
+    ready: Vec<Arc<TaskControlBlock>>,
+}
+
+impl EdfScheduler {
+    pub fn has_ready(&self) -> bool {
+        !self.ready.is_empty()
+    }
+    fn earliest_deadline(&self) -> Option<usize> {
+        self.ready
+            .iter()
+            .map(|task| task.inner_exclusive_access().rt.as_ref().unwrap().deadline)
+            .min()
+    }
+}
+
+// 被限流的任务在下一个周期开始时重新加入就绪队列
+fn replenish(task: Arc<TaskControlBlock>) {
+    let mut inner = task.inner_exclusive_access();
+    let rt = inner.rt.as_mut().unwrap();
+    rt.throttled = false;
+    rt.catch_up(get_time(), false);
+    drop(inner);
+    add_task(task);
+}
+
+impl Scheduler for EdfScheduler {
+    fn new() -> Self {
+        Self { ready: Vec::new() }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut inner = task.inner_exclusive_access();
+        let rt = inner.rt.as_mut().unwrap();
+        if rt.throttled {
+            let next_period = rt.deadline;
+            drop(inner);
+            add_timer(next_period, Box::new(move || replenish(task)));
+            return;
+        }
+        // 不在调度器中（阻塞）期间结束的周期不计为错过截止时间
+        rt.catch_up(get_time(), false);
+        drop(inner);
+        self.ready.push(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let now = get_time();
+        let idx = self
+            .ready
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, task)| {
+                let mut inner = task.inner_exclusive_access();
+                let rt = inner.rt.as_mut().unwrap();
+                rt.catch_up(now, true);
+                rt.deadline
+            })
+            .map(|(idx, _)| idx)?;
+        Some(self.ready.remove(idx))
+    }
+    // 在时钟中断中对正在运行的实时任务记账
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        let mut inner = current.inner_exclusive_access();
+        let rt = inner.rt.as_mut().unwrap();
+        rt.catch_up(get_time(), true);
+        rt.remaining = rt.remaining.saturating_sub(1);
+        if rt.remaining == 0 {
+            // 超出了本周期的预算，限流直到下一个周期
+            rt.throttled = true;
+            return true;
+        }
+        let deadline = rt.deadline;
+        drop(inner);
+        self.earliest_deadline()
+            .map_or(false, |earliest| earliest < deadline)
+    }
+}
@@ -0,0 +1,125 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use super::Scheduler;
+use crate::config::CLOCK_FREQ;
+use crate::task::task::TaskControlBlock;
+use crate::timer::get_time;
+
+// 队列层数，第 0 层优先级最高
+const MLFQ_LEVELS: usize = 4;
+// 每隔 1s 把所有任务提升回第 0 层，避免低层的任务饥饿
+// 按经过的时间而不是时钟中断的次数计算，所有 hart 的时钟中断都会到达这里
+const BOOST_INTERVAL: usize = CLOCK_FREQ;
+
+// 第 level 层的时间片长度，以时钟中断（TIME_SLICE_COUNT 对应的 10ms）为单位
+fn quantum_of(level: usize) -> usize {
+    1 << level
+}
+
+#[derive(Clone, Copy)]
+pub struct SchedEntity {
+    level: usize,
+    // 在当前层已经用掉的时钟中断数
+    ticks: usize,
+    // 上一次被提升时的 epoch，落后于调度器时说明错过了一次提升
+    epoch: usize,
+}
+
+impl SchedEntity {
+    pub fn new() -> Self {
+        Self {
+            level: 0,
+            ticks: 0,
+            epoch: 0,
+        }
+    }
+    pub fn set_priority(&mut self, _priority: usize) {}
+    // 新任务总是从最高层开始
+    pub fn for_child(&self) -> Self {
+        Self::new()
+    }
+    fn boost(&mut self, epoch: usize) {
+        self.level = 0;
+        self.ticks = 0;
+        self.epoch = epoch;
+    }
+}
+
+// 被时钟中断抢占（用完时间片）的任务下降一层，
+// 主动让出 CPU 的任务保持所在的层，因此交互式任务会停留在高层
+pub struct MlfqScheduler {
+    queues: [VecDeque<Arc<TaskControlBlock>>; MLFQ_LEVELS],
+    // 上一次提升的时间
+    last_boost: usize,
+    epoch: usize,
+}
+
+impl MlfqScheduler {
+    // 距离上一次提升超过 BOOST_INTERVAL 时提升所有就绪的任务并返回 true
+    fn boost_if_due(&mut self) -> bool {
+        let now = get_time();
+        if now - self.last_boost < BOOST_INTERVAL {
+            return false;
+        }
+        self.last_boost = now;
+        self.boost_all();
+        true
+    }
+    fn boost_all(&mut self) {
+        self.epoch += 1;
+        for level in 1..MLFQ_LEVELS {
+            while let Some(task) = self.queues[level].pop_front() {
+                task.inner_exclusive_access().sched.boost(self.epoch);
+                self.queues[0].push_back(task);
+            }
+        }
+        for task in self.queues[0].iter() {
+            task.inner_exclusive_access().sched.boost(self.epoch);
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn new() -> Self {
+        Self {
+            queues: Default::default(),
+            last_boost: get_time(),
+            epoch: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut inner = task.inner_exclusive_access();
+        // 不在队列中（被阻塞或者新创建）的任务错过的提升在这里补上
+        if inner.sched.epoch != self.epoch {
+            inner.sched.boost(self.epoch);
+        }
+        let level = inner.sched.level;
+        drop(inner);
+        self.queues[level].push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        if self.boost_if_due() {
+            current.inner_exclusive_access().sched.boost(self.epoch);
+            // 提升之后所有任务都在第 0 层，重新轮转
+            return true;
+        }
+        let mut inner = current.inner_exclusive_access();
+        let entity = &mut inner.sched;
+        entity.ticks += 1;
+        if entity.ticks < quantum_of(entity.level) {
+            // 时间片还没有用完，继续运行
+            return false;
+        }
+        // 用完了时间片，下降一层
+        entity.level = (entity.level + 1).min(MLFQ_LEVELS - 1);
+        entity.ticks = 0;
+        true
+    }
+    fn on_other_tick(&mut self) {
+        self.boost_if_due();
+    }
+}
@@ -0,0 +1,62 @@
+//! 可替换的调度策略，通过 kernel/Cargo.toml 中的 feature 选择
+//!
+//! - 默认：stride 调度
+//! - `sched_rr`：时间片轮转
+//! - `sched_cfs`：按虚拟运行时间调度的 CFS
+//! - `sched_mlfq`：多级反馈队列
+
+use alloc::sync::Arc;
+
+use super::task::TaskControlBlock;
+
+#[cfg(any(
+    all(feature = "sched_rr", feature = "sched_cfs"),
+    all(feature = "sched_rr", feature = "sched_mlfq"),
+    all(feature = "sched_cfs", feature = "sched_mlfq"),
+))]
+compile_error!("at most one of `sched_rr`, `sched_cfs` and `sched_mlfq` can be enabled");
+
+#[cfg(feature = "sched_cfs")]
+mod cfs;
+mod edf;
+#[cfg(feature = "sched_mlfq")]
+mod mlfq;
+#[cfg(feature = "sched_rr")]
+mod round_robin;
+#[cfg(not(any(feature = "sched_rr", feature = "sched_cfs", feature = "sched_mlfq")))]
+mod stride;
+
+// 实时调度类与普通任务的调度策略无关，总是存在
+pub use edf::{EdfScheduler, RtEntity};
+
+// 每个调度策略都提供自己的 SchedEntity，保存在 TCB 中，记录该策略需要的任务状态
+#[cfg(feature = "sched_cfs")]
+pub use cfs::{CfsScheduler as TaskScheduler, SchedEntity};
+#[cfg(feature = "sched_mlfq")]
+pub use mlfq::{MlfqScheduler as TaskScheduler, SchedEntity};
+#[cfg(feature = "sched_rr")]
+pub use round_robin::{RoundRobinScheduler as TaskScheduler, SchedEntity};
+#[cfg(not(any(feature = "sched_rr", feature = "sched_cfs", feature = "sched_mlfq")))]
+pub use stride::{SchedEntity, StrideScheduler as TaskScheduler};
+
+// 新任务的默认优先级，时间片轮转与多级反馈队列不使用优先级
+#[cfg(not(any(feature = "sched_rr", feature = "sched_mlfq")))]
+pub const DEFAULT_PRIORITY: usize = 16;
+
+// 调度器只管理就绪的任务，正在运行的任务由 Processor 持有
+pub trait Scheduler {
+    fn new() -> Self;
+    // 任务变为就绪：新创建的任务，或者让出 CPU 的任务
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    // 选出下一个要运行的任务
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    // 每次时钟中断时对当前任务调用，返回 true 表示需要切换到其他任务
+    fn on_tick(&mut self, current: &Arc<TaskControlBlock>) -> bool;
+    // 当前任务不由该调度器管理（正在运行实时任务）时，时钟中断改为调用这个函数
+    // 用于推进与具体任务无关的计时，例如多级反馈队列的周期性提升
+    fn on_other_tick(&mut self) {}
+    // 当前任务进入阻塞状态时调用，被唤醒时会重新通过 add 加入
+    fn on_block(&mut self, _task: &Arc<TaskControlBlock>) {}
+    // 任务退出时调用
+    fn on_exit(&mut self, _task: &Arc<TaskControlBlock>) {}
+}
@@ -0,0 +1,41 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+use super::Scheduler;
+use crate::task::task::TaskControlBlock;
+
+// 时间片轮转不需要记录额外的状态
+#[derive(Clone, Copy)]
+pub struct SchedEntity;
+
+impl SchedEntity {
+    pub fn new() -> Self {
+        Self
+    }
+    pub fn set_priority(&mut self, _priority: usize) {}
+    pub fn for_child(&self) -> Self {
+        Self
+    }
+}
+
+// 简单的 FIFO，每个时钟中断都切换
+pub struct RoundRobinScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn on_tick(&mut self, _current: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+}
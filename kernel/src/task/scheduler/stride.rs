@@ -0,0 +1,106 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+
+use super::{Scheduler, DEFAULT_PRIORITY};
+use crate::config::BIG_STRIDE;
+use crate::task::task::TaskControlBlock;
+
+fn stride_of(priority: usize) -> u64 {
+    BIG_STRIDE / priority as u64
+}
+
+// stride 调度中的 pass 值，允许溢出回绕
+// 加入就绪队列的任务的 pass 不小于当前的最小值，优先级又不小于 2，
+// 因此任意两个任务 pass 之差不超过 BIG_STRIDE / 2，远小于 i64::MAX，
+// 把差值看作有符号数就能得到正确的大小关系
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Pass(u64);
+
+impl Pass {
+    fn step(&mut self, stride: u64) {
+        self.0 = self.0.wrapping_add(stride);
+    }
+}
+
+impl PartialOrd for Pass {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some((self.0.wrapping_sub(other.0) as i64).cmp(&0))
+    }
+}
+
+// 每次被调度时 pass 增加 stride = BIG_STRIDE / priority
+#[derive(Clone, Copy)]
+pub struct SchedEntity {
+    stride: u64,
+    pass: Pass,
+}
+
+impl SchedEntity {
+    pub fn new() -> Self {
+        Self {
+            stride: stride_of(DEFAULT_PRIORITY),
+            pass: Pass(0),
+        }
+    }
+    pub fn set_priority(&mut self, priority: usize) {
+        self.stride = stride_of(priority);
+    }
+    // 子进程继承父进程的优先级与 pass，避免子进程长时间独占 CPU
+    pub fn for_child(&self) -> Self {
+        *self
+    }
+}
+
+// 每次选出 pass 最小的任务
+pub struct StrideScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    // 最近一次选出的任务的 pass，不大于任何就绪任务的 pass
+    min_pass: Pass,
+}
+
+impl Scheduler for StrideScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+            min_pass: Pass(0),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        // 被唤醒的任务以及 pass 为 0 的内核线程可能远远落后于其他任务，
+        // 从当前的最小值开始，既不会长时间独占 CPU，也不会破坏 pass 的比较
+        let mut inner = task.inner_exclusive_access();
+        if inner.sched.pass < self.min_pass {
+            inner.sched.pass = self.min_pass;
+        }
+        drop(inner);
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        // pass 相同时按照加入队列的先后顺序
+        let mut min_idx = 0;
+        let mut min_pass = self
+            .ready_queue
+            .front()?
+            .inner_exclusive_access()
+            .sched
+            .pass;
+        for (idx, task) in self.ready_queue.iter().enumerate().skip(1) {
+            let pass = task.inner_exclusive_access().sched.pass;
+            if pass < min_pass {
+                min_idx = idx;
+                min_pass = pass;
+            }
+        }
+        self.min_pass = min_pass;
+        let task = self.ready_queue.remove(min_idx)?;
+        let mut inner = task.inner_exclusive_access();
+        let stride = inner.sched.stride;
+        inner.sched.pass.step(stride);
+        drop(inner);
+        Some(task)
+    }
+    fn on_tick(&mut self, _current: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+}
@@ -1,61 +1,130 @@
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 
-use super::{context::TaskContext, switch::__switch};
-use crate::config::{kernel_stack_position, TRAP_CONTEXT};
+pub use abi::{TaskInfo, TaskStatus};
+
+use super::context::TaskContext;
+use super::pid::{pid_alloc, KernelStack, PidHandle};
+use super::scheduler::{RtEntity, SchedEntity};
+use crate::config::{MAX_SYSCALL_NUM, TRAP_CONTEXT};
 use crate::mm::address::{PhysPageNum, VirtAddr};
-use crate::mm::memory_set::{MapPermission, MemorySet};
+use crate::mm::memory_set::MemorySet;
 use crate::mm::KERNEL_SPACE;
-use crate::sync::UPSafeCell;
+use crate::sync::{SpinLock, SpinLockGuard};
 use crate::trap::context::TrapContext;
 use crate::trap::trap_handler;
 
-#[derive(Clone, Copy, PartialEq)]
-pub enum TaskStatus {
-    UnInit,
-    Ready,
-    Running,
-    Exited,
-}
-
 // TCB (Task Control Block)
+// 被 TaskManager、Processor 以及父进程共享，可变的部分放在 inner 中
 pub struct TaskControlBlock {
+    // 创建之后不再改变
+    // 字段按声明的顺序释放：先解除内核栈的映射再回收 pid，
+    // 否则其他 hart 复用这个 pid 时，同一位置的内核栈还没有解除映射
+    pub kernel_stack: KernelStack,
+    pub pid: PidHandle,
+    // 内核线程只在内核地址空间中运行，不会返回用户态
+    pub kernel_thread: bool,
+    // 运行过程中会改变
+    inner: SpinLock<TaskControlBlockInner>,
+}
+
+pub struct TaskControlBlockInner {
     pub status: TaskStatus,
     pub task_cx: TaskContext,
-    pub memory_set: MemorySet,
-    pub trap_cx_ppn: PhysPageNum,
-    pub base_size: usize, // 包括应用地址空间中的大小 以及其在堆上分配的大小
+    // 是否有 hart 正在该任务的内核栈上运行，切换出去之后由该 hart 的 idle 控制流清除
+    // 其他 hart 取出该任务后需要等到它为 false，此时 task_cx 才已经保存完毕
+    pub on_cpu: bool,
+    // 用户地址空间，内核线程只使用 KERNEL_SPACE，没有自己的地址空间
+    pub memory_set: Option<MemorySet>,
+    // trap 上下文所在的页帧，内核线程不会进入用户态，没有 trap 上下文
+    pub trap_cx_ppn: Option<PhysPageNum>,
+    // 堆的起始地址以及当前的 program break，堆占据 [heap_bottom, program_brk)
+    pub heap_bottom: usize,
+    pub program_brk: usize,
+    // 父进程使用弱引用，避免父子之间循环引用
+    pub parent: Option<Weak<TaskControlBlock>>,
+    pub children: Vec<Arc<TaskControlBlock>>,
+    pub exit_code: i32,
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    // 第一次被调度时的时间（毫秒），尚未运行过为 None
+    pub start_time: Option<usize>,
+    // 由当前选择的调度策略使用的状态
+    pub sched: SchedEntity,
+    // 实时任务的周期与预算，普通任务为 None
+    pub rt: Option<RtEntity>,
+    // 内核线程要执行的函数，开始运行时取出
+    pub kernel_thread_fn: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl TaskControlBlockInner {
+    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
+        self.trap_cx_ppn
+            .expect("[kernel] kernel thread has no trap context")
+            .get_mut()
+    }
+    pub fn get_memory_set(&mut self) -> &mut MemorySet {
+        self.memory_set
+            .as_mut()
+            .expect("[kernel] kernel thread has no user address space")
+    }
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set
+            .as_ref()
+            .expect("[kernel] kernel thread has no user address space")
+            .token()
+    }
+    pub fn is_zombie(&self) -> bool {
+        self.status == TaskStatus::Zombie
+    }
 }
 
 impl TaskControlBlock {
-    pub fn new(elf_data: &[u8], app_id: usize) -> Self {
-        let mut task_control_block;
+    pub fn inner_exclusive_access(&self) -> SpinLockGuard<'_, TaskControlBlockInner> {
+        self.inner.lock()
+    }
+    pub fn new(elf_data: &'static [u8]) -> Self {
+        let task_control_block;
         let load_result = MemorySet::load_elf(elf_data);
         match load_result {
-            Ok((memory_set, user_sp, entry_point)) => {
+            Ok((memory_set, user_sp, heap_bottom, entry_point)) => {
                 let trap_cx_ppn = memory_set
                     .get_pte(VirtAddr::from(TRAP_CONTEXT).into())
                     .ppn();
-                let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(app_id);
-                // push kernel stack
-                KERNEL_SPACE.exclusive_access().push_kernel_stack_for_app(
-                    kernel_stack_bottom.into(),
-                    kernel_stack_top.into(),
-                    MapPermission::R | MapPermission::W,
-                );
+                // 分配 pid 以及对应的内核栈
+                let pid = pid_alloc();
+                let kernel_stack = KernelStack::new(&pid);
+                let kernel_stack_top = kernel_stack.get_top();
                 task_control_block = Self {
-                    status: TaskStatus::Ready,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    memory_set,
-                    trap_cx_ppn,
-                    base_size: user_sp,
+                    pid,
+                    kernel_stack,
+                    kernel_thread: false,
+                    inner: SpinLock::new(TaskControlBlockInner {
+                        status: TaskStatus::Ready,
+                        task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                        on_cpu: false,
+                        memory_set: Some(memory_set),
+                        trap_cx_ppn: Some(trap_cx_ppn),
+                        heap_bottom,
+                        program_brk: heap_bottom,
+                        parent: None,
+                        children: Vec::new(),
+                        exit_code: 0,
+                        syscall_times: [0; MAX_SYSCALL_NUM],
+                        start_time: None,
+                        sched: SchedEntity::new(),
+                        rt: None,
+                        kernel_thread_fn: None,
+                    }),
                 };
-                task_control_block.set_trap_cx(TrapContext::app_init_context(
-                    entry_point,
-                    user_sp,
-                    KERNEL_SPACE.exclusive_access().token(),
-                    kernel_stack_top,
-                    trap_handler as usize,
-                ))
+                *task_control_block.inner_exclusive_access().get_trap_cx() =
+                    TrapContext::app_init_context(
+                        entry_point,
+                        user_sp,
+                        KERNEL_SPACE.lock().token(),
+                        kernel_stack_top,
+                        trap_handler as usize,
+                    );
             }
             Err(err) => {
                 panic!("load elf failed: {}", err);
@@ -63,85 +132,136 @@ impl TaskControlBlock {
         }
         task_control_block
     }
-    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
-        self.trap_cx_ppn.get_mut()
-    }
-    pub fn set_trap_cx(&mut self, cx: TrapContext) {
-        *(self.trap_cx_ppn.get_mut::<TrapContext>()) = cx;
-    }
-    pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
+    // 创建一个执行 f 的内核线程，与其他任务共享 KERNEL_SPACE，只需要分配 pid 和内核栈
+    pub fn new_kernel_thread(f: Box<dyn FnOnce() + Send>) -> Self {
+        let pid = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid);
+        let kernel_stack_top = kernel_stack.get_top();
+        Self {
+            pid,
+            kernel_stack,
+            kernel_thread: true,
+            inner: SpinLock::new(TaskControlBlockInner {
+                status: TaskStatus::Ready,
+                task_cx: TaskContext::goto_kernel_thread_entry(kernel_stack_top),
+                on_cpu: false,
+                memory_set: None,
+                trap_cx_ppn: None,
+                heap_bottom: 0,
+                program_brk: 0,
+                parent: None,
+                children: Vec::new(),
+                exit_code: 0,
+                syscall_times: [0; MAX_SYSCALL_NUM],
+                start_time: None,
+                sched: SchedEntity::new(),
+                rt: None,
+                kernel_thread_fn: Some(f),
+            }),
+        }
     }
-}
-
-pub struct TaskMangerInner {
-    pub tasks: Vec<TaskControlBlock>,
-    pub current_task: usize,
-}
-pub struct TaskManger {
-    pub num_app: usize,
-    pub inner: UPSafeCell<TaskMangerInner>,
-}
-
-impl TaskManger {
-    pub fn mark_current_suspended(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].status = TaskStatus::Ready;
+    // 复制当前任务的地址空间，得到一个分配了新 pid 的子任务
+    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+        let mut parent_inner = self.inner_exclusive_access();
+        let memory_set = MemorySet::from_existed_user(parent_inner.get_memory_set());
+        let trap_cx_ppn = memory_set
+            .get_pte(VirtAddr::from(TRAP_CONTEXT).into())
+            .ppn();
+        let pid = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid);
+        let kernel_stack_top = kernel_stack.get_top();
+        let task_control_block = Arc::new(Self {
+            pid,
+            kernel_stack,
+            kernel_thread: false,
+            inner: SpinLock::new(TaskControlBlockInner {
+                status: TaskStatus::Ready,
+                task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                on_cpu: false,
+                memory_set: Some(memory_set),
+                trap_cx_ppn: Some(trap_cx_ppn),
+                heap_bottom: parent_inner.heap_bottom,
+                program_brk: parent_inner.program_brk,
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                exit_code: 0,
+                syscall_times: [0; MAX_SYSCALL_NUM],
+                start_time: None,
+                sched: parent_inner.sched.for_child(),
+                // 子进程不继承实时属性
+                rt: None,
+                kernel_thread_fn: None,
+            }),
+        });
+        parent_inner.children.push(task_control_block.clone());
+        // trap context 是从父进程复制过来的，只需要修改内核栈的位置
+        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        trap_cx.kernel_sp = kernel_stack_top;
+        task_control_block
     }
-
-    pub fn mark_current_exited(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].status = TaskStatus::Exited;
+    // 直接从 ELF 创建子任务，不需要复制当前任务的地址空间
+    pub fn spawn(self: &Arc<Self>, elf_data: &'static [u8]) -> Arc<Self> {
+        let task_control_block = Arc::new(Self::new(elf_data));
+        let mut inner = task_control_block.inner_exclusive_access();
+        inner.parent = Some(Arc::downgrade(self));
+        inner.sched = self.inner_exclusive_access().sched.for_child();
+        drop(inner);
+        self.inner_exclusive_access()
+            .children
+            .push(task_control_block.clone());
+        task_control_block
     }
-
-    fn find_next_task(&self) -> Option<usize> {
-        let inner = self.inner.exclusive_access();
-        let current_task = inner.current_task;
-        (current_task + 1..self.num_app)
-            .map(|id| id % self.num_app)
-            .find(|id| inner.tasks[*id].status == TaskStatus::Ready)
+    // 用新的 ELF 替换当前任务的地址空间，内核栈保持不变
+    pub fn exec(&self, elf_data: &'static [u8]) {
+        let (memory_set, user_sp, heap_bottom, entry_point) = match MemorySet::load_elf(elf_data) {
+            Ok(result) => result,
+            Err(err) => panic!("load elf failed: {}", err),
+        };
+        let trap_cx_ppn = memory_set
+            .get_pte(VirtAddr::from(TRAP_CONTEXT).into())
+            .ppn();
+        let mut inner = self.inner_exclusive_access();
+        inner.memory_set = Some(memory_set);
+        inner.trap_cx_ppn = Some(trap_cx_ppn);
+        inner.heap_bottom = heap_bottom;
+        inner.program_brk = heap_bottom;
+        *inner.get_trap_cx() = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.lock().token(),
+            self.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
     }
-
-    pub fn run_next_task(&self) {
-        if let Some(next_task) = self.find_next_task() {
-            let mut inner = self.inner.exclusive_access();
-            let last_task = inner.current_task;
-            inner.tasks[next_task].status = TaskStatus::Running;
-            inner.current_task = next_task;
-            let last_task_cx_ptr = &mut inner.tasks[last_task].task_cx as *mut TaskContext;
-            let next_task_cx_ptr = &mut inner.tasks[next_task].task_cx as *const TaskContext;
-            drop(inner);
-            unsafe {
-                __switch(last_task_cx_ptr, next_task_cx_ptr);
-            }
-        } else {
-            panic!("no task to run, may be All application suspended/exited");
+    // 将 program break 移动 size 字节，返回原来的 program break
+    // 不能缩小到堆的起始地址以下，扩展时与其他区域重叠也会失败
+    pub fn change_program_brk(&self, size: isize) -> Option<usize> {
+        let mut inner = self.inner_exclusive_access();
+        let heap_bottom = inner.heap_bottom;
+        let old_brk = inner.program_brk;
+        let new_brk = (old_brk as isize).checked_add(size)?;
+        if new_brk < heap_bottom as isize {
+            return None;
         }
-    }
-    pub fn run_first_task(&self) -> ! {
-        let mut inner = self.inner.exclusive_access();
-        let first_task = &mut inner.tasks[0];
-        first_task.status = TaskStatus::Running;
-        let next_task_cx_ptr = &first_task.task_cx as *const TaskContext;
-        drop(inner);
-        let mut _unused = TaskContext::zero_init();
-        unsafe {
-            __switch(&mut _unused as *mut TaskContext, next_task_cx_ptr);
+        let new_brk = new_brk as usize;
+        let heap_start = VirtAddr::from(heap_bottom).floor();
+        let old_end = VirtAddr::from(old_brk).ceil();
+        let new_end = VirtAddr::from(new_brk).ceil();
+        let result = if new_end > old_end {
+            inner.get_memory_set().append_to(heap_start, new_end)
+        } else {
+            inner.get_memory_set().shrink_to(heap_start, new_end)
+        };
+        if !result {
+            return None;
         }
-        panic!("[Kernel] [run_first_task]should not reach here");
-    }
-
-    pub fn get_current_task(&self) -> usize {
-        self.inner.exclusive_access().current_task
+        inner.program_brk = new_brk;
+        Some(old_brk)
     }
-    pub fn get_current_token(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_user_token()
+    pub fn set_priority(&self, priority: usize) {
+        self.inner_exclusive_access().sched.set_priority(priority);
     }
-    pub fn get_current_trap_cx(&self) -> &mut TrapContext {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_trap_cx()
+    pub fn getpid(&self) -> usize {
+        self.pid.0
     }
 }
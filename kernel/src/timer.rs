@@ -1,20 +1,138 @@
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
 use riscv::register::time;
 
-use crate::{config::CLOCK_FREQ, sbi::set_timer};
+use crate::{
+    config::{CLOCK_FREQ, MAX_HARTS},
+    sbi::set_timer,
+    smp::hart_id,
+    sync::SpinLock,
+};
 
 // 用来获得 mtimer 的值
 pub fn get_time() -> usize {
     time::read()
 }
-const TIME_SLICE_COUNT: usize = 100;
+// 每秒的时钟中断次数，即时间片为 10ms
+pub const TIME_SLICE_COUNT: usize = 100;
+
+// 一个定时事件：到达 deadline（mtime 的值）之后在时钟中断中执行 callback
+struct TimerEvent {
+    deadline: usize,
+    // deadline 相同时按加入的顺序执行
+    seq: usize,
+    callback: Box<dyn FnOnce() + Send>,
+}
+
+impl PartialEq for TimerEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline && self.seq == other.seq
+    }
+}
+
+impl Eq for TimerEvent {}
+
+impl PartialOrd for TimerEvent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimerEvent {
+    // BinaryHeap 是大根堆，反过来比较使最早到期的事件位于堆顶
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.deadline, other.seq).cmp(&(self.deadline, self.seq))
+    }
+}
+
+// 调度时钟和所有定时事件共用一个 SBI 定时器，每次都按最早的那个设置 mtimecmp
+// 每个 hart 有自己的定时器与调度时钟，定时事件由先处理到它的 hart 执行
+struct TimerQueue {
+    events: BinaryHeap<TimerEvent>,
+    next_seq: usize,
+    // 每个 hart 下一次调度时钟到期的时间
+    next_tick: [usize; MAX_HARTS],
+}
+
+impl TimerQueue {
+    fn new() -> Self {
+        Self {
+            events: BinaryHeap::new(),
+            next_seq: 0,
+            next_tick: [0; MAX_HARTS],
+        }
+    }
+    // 设置当前 hart 的定时器
+    fn program(&self) {
+        let next_tick = self.next_tick[hart_id()];
+        let next = match self.events.peek() {
+            Some(event) => event.deadline.min(next_tick),
+            None => next_tick,
+        };
+        // 一旦计数器 mtime 的值超过了 mtimecmp，就会触发中断；我们这个操作是设置 mtimecmp 的值
+        set_timer(next);
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref TIMER_QUEUE: SpinLock<TimerQueue> = SpinLock::new(TimerQueue::new());
+}
+
 pub fn set_next_trigger() {
-    // 间隔 10ms 触发一次时钟中断，而由于我们已经将 sie寄存器等正确设置，中断接收，进入 trap_handler 中处理
-    // 一旦计数器 mtime 的值超过了 mtimecmp，就会触发中断；我们这个操作是设置 mtimecmp 的值
-    set_timer(get_time() + CLOCK_FREQ / TIME_SLICE_COUNT);
+    // 间隔 10ms 触发一次调度时钟，而由于我们已经将 sie寄存器等正确设置，中断接收，进入 trap_handler 中处理
+    let mut queue = TIMER_QUEUE.lock();
+    queue.next_tick[hart_id()] = get_time() + CLOCK_FREQ / TIME_SLICE_COUNT;
+    queue.program();
 }
 
+// 在 deadline 之后的时钟中断中执行 callback
+pub fn add_timer(deadline: usize, callback: Box<dyn FnOnce() + Send>) {
+    let mut queue = TIMER_QUEUE.lock();
+    let seq = queue.next_seq;
+    queue.next_seq += 1;
+    queue.events.push(TimerEvent {
+        deadline,
+        seq,
+        callback,
+    });
+    queue.program();
+}
+
+// 时钟中断时调用：执行所有已经到期的事件，返回调度时钟是否到期
+pub fn handle_timer_interrupt() -> bool {
+    let now = get_time();
+    let mut queue = TIMER_QUEUE.lock();
+    let mut expired = Vec::new();
+    while queue
+        .events
+        .peek()
+        .map_or(false, |event| event.deadline <= now)
+    {
+        expired.push(queue.events.pop().unwrap());
+    }
+    let hart = hart_id();
+    let tick = now >= queue.next_tick[hart];
+    if tick {
+        queue.next_tick[hart] = now + CLOCK_FREQ / TIME_SLICE_COUNT;
+    }
+    queue.program();
+    // 回调中可能会再添加定时事件，需要先释放 TIMER_QUEUE
+    drop(queue);
+    for event in expired {
+        (event.callback)();
+    }
+    tick
+}
 
-// 返回微秒时间
+// 返回毫秒时间
 pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / 1000)
 }
+
+// 将毫秒数转换为 mtime 的计数
+pub fn ms_to_ticks(ms: usize) -> usize {
+    ms.saturating_mul(CLOCK_FREQ / 1000)
+}
@@ -8,6 +8,7 @@ pub struct TrapContext {
     pub kernel_satp: usize,  // 内核页表的起始物理地址
     pub kernel_sp: usize,    // 应用在内核栈中栈顶 在内核地址空间中的虚拟地址
     pub trap_handler: usize, // 内核地址空间中，trap_handler 的虚拟地址
+    pub hart_id: usize,      // 运行该任务的 hart，进入内核时由 __alltraps 放入 tp
 }
 
 impl TrapContext {
@@ -31,6 +32,7 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            hart_id: 0,
         };
         cx.set_sp(sp);
         cx
@@ -5,57 +5,104 @@ use core::arch::{asm, global_asm};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, stval, stvec,
+    sie, sscratch, stval, stvec,
 };
 
 use crate::{
     config::{TRAMPOLINE, TRAP_CONTEXT},
+    mm::{address::VirtAddr, memory_set::MapPermission},
+    smp::hart_id,
     syscall::syscall,
     task::{
-        current_tasktoken, current_trap_cx, exit_current_and_run_next,
-        suspended_current_and_run_next,
+        current_task, current_tasktoken, current_trap_cx, exit_current_and_run_next,
+        handle_current_page_fault, tick_current_and_run_next,
     },
-    timer::set_next_trigger,
+    timer::handle_timer_interrupt,
 };
 
 global_asm!(include_str!("trap.S"));
 
 pub fn init() {
+    set_kernel_trap_entry();
+}
+
+// 进入内核之后，trap 由 __alltraps_k 保存上下文后交给 trap_from_kernel 处理
+fn set_kernel_trap_entry() {
     extern "C" {
-        fn __alltraps();
+        fn __alltraps_k();
+    }
+    unsafe {
+        stvec::write(__alltraps_k as usize, TrapMode::Direct);
+        sscratch::write(trap_from_kernel as usize);
     }
-    unsafe { stvec::write(__alltraps as usize, TrapMode::Direct) }
 }
 
 #[no_mangle]
-pub fn trap_from_kernel() -> ! {
-    panic!("a trap from kernel!");
+pub fn trap_from_kernel(_trap_cx: &context::TrapContext) {
+    let scause = scause::read();
+    let stval = stval::read();
+    match scause.cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            // 处理到期的定时事件（例如唤醒睡眠的任务）
+            // 内核中只有 idle 控制流与内核线程会打开中断，被打断的是内核线程时可以抢占
+            if handle_timer_interrupt() && current_task().is_some() {
+                tick_current_and_run_next();
+            }
+        }
+        _ => {
+            panic!(
+                "Unsupported trap from kernel: {:?}, stval = {:#x}!",
+                scause.cause(),
+                stval
+            );
+        }
+    }
 }
 
 #[no_mangle]
-pub fn trap_handler() -> &'static mut context::TrapContext {
-    unsafe {
-        stvec::write(trap_from_kernel as usize, TrapMode::Direct);
-    }
-    let cx = current_trap_cx();
+pub fn trap_handler() -> ! {
+    set_kernel_trap_entry();
     let scause = scause::read();
     let stval = stval::read();
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
+            let mut cx = current_trap_cx();
             cx.sepc += 4;
-            cx.x[10] = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]) as usize;
+            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            // exec 会替换掉地址空间，原来的 trap context 已经失效，需要重新获取
+            cx = current_trap_cx();
+            cx.x[10] = result as usize;
+        }
+        Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            // 访问按需分配、还没有页帧的页时分配页帧，之后重新执行触发缺页的指令
+            let access = match scause.cause() {
+                Trap::Exception(Exception::LoadPageFault) => MapPermission::R,
+                Trap::Exception(Exception::StorePageFault) => MapPermission::W,
+                _ => MapPermission::X,
+            };
+            if !handle_current_page_fault(VirtAddr::from(stval).floor(), access) {
+                println!(
+                    "[kernel] PageFault in application, bad addr = {:#x}, kernel killed it.",
+                    stval
+                );
+                exit_current_and_run_next(-2);
+            }
         }
-        Trap::Exception(Exception::StoreFault) | Trap::Exception(Exception::StorePageFault) => {
+        Trap::Exception(Exception::StoreFault) => {
             println!("[kernel] PageFault in application, kernel killed it.");
-            exit_current_and_run_next();
+            exit_current_and_run_next(-2);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
             println!("[kernel] IllegalInstruction in application, kernel killed it.");
-            exit_current_and_run_next();
+            exit_current_and_run_next(-3);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            set_next_trigger();
-            suspended_current_and_run_next();
+            // 只有调度时钟到期才交给调度器，定时事件到期不影响当前任务
+            if handle_timer_interrupt() {
+                tick_current_and_run_next();
+            }
         }
         _ => {
             panic!(
@@ -65,7 +112,7 @@ pub fn trap_handler() -> &'static mut context::TrapContext {
             );
         }
     }
-    cx
+    trap_return();
 }
 
 pub fn trap_return() -> ! {
@@ -73,6 +120,8 @@ pub fn trap_return() -> ! {
         stvec::write(TRAMPOLINE as usize, TrapMode::Direct);
     }
     let trap_cx_ptr = TRAP_CONTEXT;
+    // 任务每次返回用户态时都可能在不同的 hart 上，记下当前的 hartid，下次 trap 时恢复到 tp
+    current_trap_cx().hart_id = hart_id();
     let user_satp = current_tasktoken();
     extern "C" {
         fn __alltraps();
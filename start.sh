@@ -7,6 +7,8 @@ fi
 
 BOOTLOADER=../bootloader/rustsbi-qemu.bin
 FS_IMG=target/riscv64gc-unknown-none-elf/release/kernel.bin
+# 交换区使用的块设备镜像
+SWAP_IMG=target/riscv64gc-unknown-none-elf/release/swap.img
 DOCKER_NAME=dinghao188/rcore-tutorial
 
 case ${1} in
@@ -20,6 +22,8 @@ case ${1} in
     cargo build --release
     echo "Strip target"
     rust-objcopy --strip-all target/riscv64gc-unknown-none-elf/release/kernel -O binary target/riscv64gc-unknown-none-elf/release/kernel.bin
+    echo "Create swap image"
+    dd if=/dev/zero of=${SWAP_IMG} bs=1M count=128
     echo 'Over Complied'
     ;;
 
@@ -27,17 +31,25 @@ case ${1} in
     echo "Start Runing in QEMU"
     qemu-system-riscv64 \
     -machine virt \
+    -m 64M \
+    -smp 4 \
     -nographic \
     -bios ${BOOTLOADER} \
-    -device loader,file=${FS_IMG},addr=0x80200000
+    -device loader,file=${FS_IMG},addr=0x80200000 \
+    -drive file=${SWAP_IMG},if=none,format=raw,id=swap0 \
+    -device virtio-blk-device,drive=swap0,bus=virtio-mmio-bus.0
     ;;
 "debug")
     echo "Start Debug in QEMU"
     qemu-system-riscv64 \
     -machine virt \
+    -m 64M \
+    -smp 4 \
     -nographic \
     -bios ${BOOTLOADER} \
     -device loader,file=${FS_IMG},addr=0x80200000 \
+    -drive file=${SWAP_IMG},if=none,format=raw,id=swap0 \
+    -device virtio-blk-device,drive=swap0,bus=virtio-mmio-bus.0 \
     -s -S
     ;;
 'docker')
@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_fork, sys_mmap};
+use user::waitpid;
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x1000_0000;
+const PAGES: usize = 16;
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+
+static mut DATA: usize = 1;
+
+fn page(i: usize) -> *mut usize {
+    (START + i * PAGE_SIZE) as *mut usize
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(sys_mmap(START, PAGES * PAGE_SIZE, PROT_READ | PROT_WRITE), 0);
+    for i in 0..PAGES {
+        unsafe { page(i).write_volatile(i) };
+    }
+    let pid = sys_fork();
+    if pid == 0 {
+        // 子进程写入之后得到自己的副本，不影响父进程
+        for i in 0..PAGES {
+            assert_eq!(unsafe { page(i).read_volatile() }, i);
+            unsafe { page(i).write_volatile(i + 100) };
+        }
+        unsafe { DATA = 2 };
+        for i in 0..PAGES {
+            assert_eq!(unsafe { page(i).read_volatile() }, i + 100);
+        }
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(unsafe { DATA }, 1);
+    // 子进程退出之后页帧只剩父进程一个引用，写入时不再复制
+    for i in 0..PAGES {
+        assert_eq!(unsafe { page(i).read_volatile() }, i);
+        unsafe { page(i).write_volatile(i + 200) };
+        assert_eq!(unsafe { page(i).read_volatile() }, i + 200);
+    }
+    println!("Test copy on write OK!");
+    0
+}
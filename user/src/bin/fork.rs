@@ -0,0 +1,21 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_fork, sys_yield};
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = sys_fork();
+    if pid == 0 {
+        // 子进程
+        println!("I am child, fork returned {}", pid);
+    } else {
+        println!("I am parent, forked a child with pid = {}", pid);
+        sys_yield();
+    }
+    println!("Test fork OK!");
+    0
+}
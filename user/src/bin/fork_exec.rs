@@ -0,0 +1,22 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_exec, sys_fork};
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = sys_fork();
+    if pid == 0 {
+        // 子进程变成 hello_world，正常情况下 exec 不会返回
+        if sys_exec("hello_world\0") == -1 {
+            println!("exec hello_world failed!");
+        }
+    } else {
+        assert_eq!(sys_exec("not_exist\0"), -1);
+        println!("Test fork_exec OK!");
+    }
+    0
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_fork, sys_mmap, sys_munmap, sys_waitpid, sys_write};
+use user::waitpid;
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x1000_0000;
+// 远大于物理内存，只有访问过的页才会分配页帧
+const LEN: usize = 1 << 30;
+const STRIDE: usize = 16 << 20;
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(sys_mmap(START, LEN, PROT_READ | PROT_WRITE), 0);
+    // 第一次访问时分配的页是清零的
+    for addr in (START..START + LEN).step_by(STRIDE) {
+        let p = addr as *mut usize;
+        assert_eq!(unsafe { p.read_volatile() }, 0);
+        unsafe { p.write_volatile(addr) };
+    }
+    // 跨页的读写
+    let p = (START + PAGE_SIZE - 4) as *mut u64;
+    unsafe { p.write_unaligned(0x1234_5678_9abc_def0) };
+    assert_eq!(unsafe { p.read_unaligned() }, 0x1234_5678_9abc_def0);
+    // 子进程中能看到父进程已经写入的内容，没有访问过的页仍然按需分配
+    let pid = sys_fork();
+    if pid == 0 {
+        for addr in (START + STRIDE..START + LEN).step_by(STRIDE) {
+            assert_eq!(unsafe { (addr as *const usize).read_volatile() }, addr);
+        }
+        assert_eq!(unsafe { ((START + STRIDE / 2) as *const usize).read_volatile() }, 0);
+        return 0;
+    }
+    // 内核不能替用户写入只读的页，系统调用失败而不是回收子进程
+    assert_eq!(sys_waitpid(pid, main as fn() -> i32 as usize as *mut i32), -1);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // 解除映射时没有分配过页帧的页直接跳过
+    assert_eq!(sys_munmap(START, LEN), 0);
+    // 系统调用中传入没有映射的地址时返回 -1
+    let unmapped = unsafe { core::slice::from_raw_parts(START as *const u8, 8) };
+    assert_eq!(sys_write(1, unmapped), -1);
+    println!("Test lazy alloc OK!");
+    0
+}
@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_mmap, sys_munmap};
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x1000_0000;
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+
+fn fill(start: usize, len: usize) {
+    for addr in (start..start + len).step_by(8) {
+        unsafe { (addr as *mut usize).write_volatile(addr) };
+    }
+}
+
+fn check(start: usize, len: usize) {
+    for addr in (start..start + len).step_by(8) {
+        assert_eq!(unsafe { (addr as *const usize).read_volatile() }, addr);
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let prot = PROT_READ | PROT_WRITE;
+    // 参数检查：start 需要页对齐，prot 不能为 0 也不能有多余的位
+    assert_eq!(sys_mmap(START + 1, PAGE_SIZE, prot), -1);
+    assert_eq!(sys_mmap(START, PAGE_SIZE, 0), -1);
+    assert_eq!(sys_mmap(START, PAGE_SIZE, 0x8 | prot), -1);
+    // 映射 4 页并读写
+    assert_eq!(sys_mmap(START, 4 * PAGE_SIZE, prot), 0);
+    fill(START, 4 * PAGE_SIZE);
+    check(START, 4 * PAGE_SIZE);
+    // 与已有的映射重叠
+    assert_eq!(sys_mmap(START + 3 * PAGE_SIZE, 2 * PAGE_SIZE, prot), -1);
+    // 解除中间两页的映射，区域被切成两段，两端仍然可以访问
+    assert_eq!(sys_munmap(START + PAGE_SIZE, 2 * PAGE_SIZE), 0);
+    check(START, PAGE_SIZE);
+    check(START + 3 * PAGE_SIZE, PAGE_SIZE);
+    // 范围中有未映射的页
+    assert_eq!(sys_munmap(START, 2 * PAGE_SIZE), -1);
+    // 空出来的两页可以重新映射
+    assert_eq!(sys_mmap(START + PAGE_SIZE, 2 * PAGE_SIZE, prot), 0);
+    fill(START + PAGE_SIZE, 2 * PAGE_SIZE);
+    assert_eq!(sys_munmap(START, 4 * PAGE_SIZE), 0);
+    // 只写的映射同时可读
+    assert_eq!(sys_mmap(START, PAGE_SIZE, PROT_WRITE), 0);
+    fill(START, PAGE_SIZE);
+    check(START, PAGE_SIZE);
+    assert_eq!(sys_munmap(START, PAGE_SIZE), 0);
+    // 很大的范围中只有一页已经映射
+    assert_eq!(sys_mmap(START, PAGE_SIZE, prot), 0);
+    assert_eq!(sys_munmap(START, 1 << 37), -1);
+    assert_eq!(sys_munmap(START, PAGE_SIZE), 0);
+    println!("Test mmap OK!");
+    0
+}
@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_get_time, sys_sched_setattr, sys_sleep, sys_task_info, TaskInfo};
+
+const PERIOD: usize = 100;
+const BUDGET: usize = 20;
+
+#[no_mangle]
+fn main() -> i32 {
+    // 预算不能为 0，也不能超过周期
+    assert_eq!(sys_sched_setattr(PERIOD, 0), -1);
+    assert_eq!(sys_sched_setattr(PERIOD, PERIOD + 1), -1);
+    // 过大的参数在换算时会溢出
+    assert_eq!(sys_sched_setattr(usize::MAX, BUDGET), -1);
+    assert_eq!(sys_sched_setattr(usize::MAX, usize::MAX), -1);
+    assert_eq!(sys_sched_setattr(PERIOD, BUDGET), 0);
+    // 每个周期只做少量工作，然后睡眠到下一个周期
+    for round in 0..5 {
+        let start = sys_get_time();
+        while sys_get_time() < start + 2 {}
+        println!("control loop round {}", round);
+        sys_sleep(PERIOD);
+    }
+    let mut info = TaskInfo::new();
+    assert_eq!(sys_task_info(&mut info), 0);
+    assert_eq!(info.deadline_misses, 0);
+    println!("Test realtime OK!");
+    0
+}
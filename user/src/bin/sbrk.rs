@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::sys_sbrk;
+
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+fn main() -> i32 {
+    let heap_bottom = sys_sbrk(0);
+    assert!(heap_bottom > 0);
+    let heap_bottom = heap_bottom as usize;
+    // 扩展两页多一点，返回原来的 program break
+    let size = 2 * PAGE_SIZE + 8;
+    assert_eq!(sys_sbrk(size as i32), heap_bottom as isize);
+    assert_eq!(sys_sbrk(0), (heap_bottom + size) as isize);
+    for addr in (heap_bottom..heap_bottom + size).step_by(8) {
+        unsafe { (addr as *mut usize).write_volatile(addr) };
+    }
+    for addr in (heap_bottom..heap_bottom + size).step_by(8) {
+        assert_eq!(unsafe { (addr as *const usize).read_volatile() }, addr);
+    }
+    // 缩小一页，剩下的部分仍然可以访问
+    assert_eq!(sys_sbrk(-(PAGE_SIZE as i32)), (heap_bottom + size) as isize);
+    assert_eq!(
+        unsafe { (heap_bottom as *const usize).read_volatile() },
+        heap_bottom
+    );
+    // 不能缩小到堆的起始地址以下
+    assert_eq!(sys_sbrk(-(2 * PAGE_SIZE as i32)), -1);
+    assert_eq!(sys_sbrk(0), (heap_bottom + PAGE_SIZE + 8) as isize);
+    assert_eq!(sys_sbrk(-((PAGE_SIZE + 8) as i32)), (heap_bottom + PAGE_SIZE + 8) as isize);
+    assert_eq!(sys_sbrk(0), heap_bottom as isize);
+    println!("Test sbrk OK!");
+    0
+}
@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_fork, sys_get_time, sys_set_priority};
+use user::waitpid;
+
+// 每种优先级的子进程个数，两种加起来多于 hart 数，子进程之间才会争抢 CPU
+const CHILDREN_PER_PRIORITY: usize = 4;
+const LOW_PRIORITY: isize = 4;
+const HIGH_PRIORITY: isize = 16;
+const RUN_MS: isize = 3000;
+
+// 在 [start, end) 期间不断计数，返回以千次为单位的计数
+fn count_until(start: isize, end: isize) -> i32 {
+    while sys_get_time() < start {}
+    let mut count: usize = 0;
+    loop {
+        count += 1;
+        if count % 1000 == 0 && sys_get_time() >= end {
+            return (count / 1000) as i32;
+        }
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(sys_set_priority(0), -1);
+    assert_eq!(sys_set_priority(1), -1);
+    assert_eq!(sys_set_priority(-5), -1);
+    assert_eq!(sys_set_priority(2), 2);
+    assert_eq!(sys_set_priority(16), 16);
+    // 所有子进程在同一时刻开始计数，优先级高的子进程应当更多地被调度，计数也更多
+    let start = sys_get_time() + 100;
+    let mut pids = [(0isize, 0isize); CHILDREN_PER_PRIORITY * 2];
+    for (i, (pid, priority)) in pids.iter_mut().enumerate() {
+        *priority = if i % 2 == 0 { LOW_PRIORITY } else { HIGH_PRIORITY };
+        *pid = sys_fork();
+        if *pid == 0 {
+            sys_set_priority(*priority);
+            return count_until(start, start + RUN_MS);
+        }
+    }
+    let mut low = 0;
+    let mut high = 0;
+    for (pid, priority) in pids {
+        let mut count = 0;
+        assert_eq!(waitpid(pid, &mut count), pid);
+        println!("child with priority {} counted {}k", priority, count);
+        if priority == HIGH_PRIORITY {
+            high += count;
+        } else {
+            low += count;
+        }
+    }
+    // 理想情况下比例为 HIGH_PRIORITY / LOW_PRIORITY，
+    // 多个 hart 同时运行时各 hart 上的任务组合不固定，这里只要求高优先级的计数更多
+    assert!(high > low, "high {}k, low {}k", high, low);
+    println!("Test set_priority OK!");
+    0
+}
@@ -1,17 +1,19 @@
 #![no_std]
 #![no_main]
 
-
 #[macro_use]
 extern crate user;
-use user::syscall::sys_get_time;
+use user::syscall::{sys_get_time, sys_sleep};
 
 #[no_mangle]
-fn main() {
-    let current_timer = sys_get_time();
-    let wait_for = current_timer + 3000;
-    while sys_get_time() < wait_for {
-        println!("Tick!");
-    }
+fn main() -> i32 {
+    let start = sys_get_time();
+    println!("Sleep for 3000ms...");
+    // 睡眠期间内核不会调度该任务
+    sys_sleep(3000);
+    let elapsed = sys_get_time() - start;
+    assert!(elapsed >= 3000, "woke up after only {}ms", elapsed);
+    println!("Woke up after {}ms.", elapsed);
     println!("Test sleep OK!");
-}
\ No newline at end of file
+    0
+}
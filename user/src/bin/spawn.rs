@@ -0,0 +1,20 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::sys_spawn;
+use user::waitpid;
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(sys_spawn("not_exist\0"), -1);
+    let pid = sys_spawn("power\0");
+    assert!(pid > 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    println!("spawned child {} exited with code {}", pid, exit_code);
+    println!("Test spawn OK!");
+    0
+}
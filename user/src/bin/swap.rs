@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_fork, sys_mmap, sys_munmap, sys_task_info, TaskInfo, TaskStatus};
+use user::waitpid;
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x1000_0000;
+// 96MiB，超过 start.sh 中给 QEMU 的 64MiB 物理内存，只有换出一部分页才能完成
+const PAGES: usize = 24576;
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+// fork 之前只保留前面的一部分页，释放出的内存留给子进程的页表与内核栈
+const SHARED_PAGES: usize = 256;
+
+fn page(i: usize) -> *mut usize {
+    (START + i * PAGE_SIZE) as *mut usize
+}
+
+// 每页的开头与结尾各写一个值，检查换入的内容是否完整
+fn write_page(i: usize, value: usize) {
+    unsafe {
+        page(i).write_volatile(value);
+        page(i).add(PAGE_SIZE / 8 - 1).write_volatile(!value);
+    }
+}
+
+fn check_page(i: usize, value: usize) {
+    unsafe {
+        assert_eq!(page(i).read_volatile(), value);
+        assert_eq!(page(i).add(PAGE_SIZE / 8 - 1).read_volatile(), !value);
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(sys_mmap(START, PAGES * PAGE_SIZE, PROT_READ | PROT_WRITE), 0);
+    for i in 0..PAGES {
+        write_page(i, i);
+    }
+    for i in 0..PAGES {
+        check_page(i, i);
+    }
+    println!("{} MiB written and read back", PAGES * PAGE_SIZE >> 20);
+    // 第 0 页已被换出，读回之后与交换区中的副本一致；内核写入之后再次换出时需要重新写入交换区
+    check_page(0, 0);
+    let info = page(0) as *mut TaskInfo;
+    assert_eq!(sys_task_info(unsafe { &mut *info }), 0);
+    for i in 1..PAGES {
+        check_page(i, i);
+    }
+    let status = unsafe { core::ptr::addr_of!((*info).status).read_volatile() };
+    assert_eq!(status, TaskStatus::Running);
+    write_page(0, 0);
+    assert_eq!(
+        sys_munmap(START + SHARED_PAGES * PAGE_SIZE, (PAGES - SHARED_PAGES) * PAGE_SIZE),
+        0
+    );
+    // 子进程与父进程共享换出的页，各自的修改互不影响
+    let pid = sys_fork();
+    if pid == 0 {
+        for i in (0..SHARED_PAGES).step_by(7) {
+            check_page(i, i);
+            write_page(i, i + PAGES);
+        }
+        for i in (0..SHARED_PAGES).step_by(7) {
+            check_page(i, i + PAGES);
+        }
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    for i in 0..SHARED_PAGES {
+        check_page(i, i);
+    }
+    assert_eq!(sys_munmap(START, SHARED_PAGES * PAGE_SIZE), 0);
+    println!("Test swap OK!");
+    0
+}
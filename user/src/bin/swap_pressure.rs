@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_fork, sys_mmap, sys_munmap};
+use user::waitpid;
+
+const PAGE_SIZE: usize = 4096;
+const START: usize = 0x1000_0000;
+// 每个子进程 40MiB，单独都能放进 start.sh 中给 QEMU 的 64MiB 物理内存，加起来则放不下
+const PAGES: usize = 10240;
+const CHILDREN: usize = 2;
+const ROUNDS: usize = 3;
+const PROT_READ: usize = 1;
+const PROT_WRITE: usize = 2;
+
+fn page(i: usize) -> *mut usize {
+    (START + i * PAGE_SIZE) as *mut usize
+}
+
+// 子进程同时反复写入、读回自己的页，内存不足时只能换出自己的页
+fn run(id: usize) -> i32 {
+    assert_eq!(sys_mmap(START, PAGES * PAGE_SIZE, PROT_READ | PROT_WRITE), 0);
+    for round in 0..ROUNDS {
+        let base = (id * ROUNDS + round) * PAGES;
+        for i in 0..PAGES {
+            unsafe { page(i).write_volatile(base + i) };
+        }
+        for i in 0..PAGES {
+            assert_eq!(unsafe { page(i).read_volatile() }, base + i);
+        }
+    }
+    assert_eq!(sys_munmap(START, PAGES * PAGE_SIZE), 0);
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // 在 fork 之后才映射，页帧不与父进程共享
+    let mut pids = [0isize; CHILDREN];
+    for (id, pid) in pids.iter_mut().enumerate() {
+        *pid = sys_fork();
+        if *pid == 0 {
+            return run(id);
+        }
+    }
+    for pid in pids {
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    println!("Test swap pressure OK!");
+    0
+}
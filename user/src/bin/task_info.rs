@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_get_time, sys_task_info, sys_yield, TaskInfo, TaskStatus};
+
+const SYSCALL_WRITE: usize = 64;
+const SYSCALL_YIELD: usize = 124;
+const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_TASK_INFO: usize = 410;
+
+// 两次查询之间调用 sys_yield 的次数
+const YIELDS: u32 = 10;
+
+#[no_mangle]
+fn main() -> i32 {
+    let start = sys_get_time();
+    while sys_get_time() < start + 100 {
+        sys_yield();
+    }
+    println!("collecting task info...");
+    let mut info = TaskInfo::new();
+    assert_eq!(sys_task_info(&mut info), 0);
+    assert_eq!(info.status, TaskStatus::Running);
+    // 库函数可能自行发起系统调用，这里只检查下界
+    assert!(info.syscall_times[SYSCALL_WRITE] >= 1);
+    assert!(info.syscall_times[SYSCALL_TASK_INFO] >= 1);
+    assert!(info.syscall_times[SYSCALL_GET_TIME] >= 2);
+    assert!(info.time >= 100);
+    // 两次查询之间的系统调用都是这里发起的，计数的差值是确定的
+    for _ in 0..YIELDS {
+        sys_yield();
+    }
+    sys_get_time();
+    let mut next = TaskInfo::new();
+    assert_eq!(sys_task_info(&mut next), 0);
+    let diff = |id: usize| next.syscall_times[id] - info.syscall_times[id];
+    assert_eq!(diff(SYSCALL_YIELD), YIELDS);
+    assert_eq!(diff(SYSCALL_GET_TIME), 1);
+    assert_eq!(diff(SYSCALL_TASK_INFO), 1);
+    assert_eq!(diff(SYSCALL_WRITE), 0);
+    assert!(next.time >= info.time);
+    println!("Test task_info OK!");
+    0
+}
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use core::arch::asm;
+use user::syscall::{sys_get_time, sys_yield};
+
+const MAGIC: usize = 0xdead_beef;
+
+fn read_tp() -> usize {
+    let tp: usize;
+    unsafe {
+        asm!("mv {}, tp", out(reg) tp);
+    }
+    tp
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // 内核在 tp 中保存 hartid，用户程序修改 tp 之后陷入内核不能影响内核，
+    // 返回用户态时 tp 也要恢复为用户设置的值
+    unsafe {
+        asm!("mv tp, {}", in(reg) MAGIC);
+    }
+    for _ in 0..10 {
+        sys_yield();
+    }
+    // 忙等期间会被时钟中断打断
+    let start = sys_get_time();
+    while sys_get_time() < start + 100 {}
+    assert_eq!(read_tp(), MAGIC);
+    println!("Test user_tp OK!");
+    0
+}
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user;
+
+use user::syscall::{sys_exit, sys_fork, sys_waitpid};
+use user::{wait, waitpid};
+
+const CHILD_NUM: usize = 3;
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut pids = [0isize; CHILD_NUM];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = sys_fork();
+        if *pid == 0 {
+            // 子进程以 100 + i 退出
+            sys_exit(100 + i as i32);
+        }
+    }
+    // 不是自己的子进程
+    assert_eq!(sys_waitpid(0, core::ptr::null_mut()), -1);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pids[0], &mut exit_code), pids[0]);
+    assert_eq!(exit_code, 100);
+    for _ in 1..CHILD_NUM {
+        let pid = wait(&mut exit_code);
+        assert!(pids.contains(&pid));
+        println!("child {} exited with code {}", pid, exit_code);
+    }
+    // 所有子进程都已被回收
+    assert_eq!(wait(&mut exit_code), -1);
+    println!("Test waitpid OK!");
+    0
+}
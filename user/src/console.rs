@@ -1,6 +1,7 @@
-use crate::syscall::sys_write;
+use crate::syscall::{sys_read, sys_write};
 use core::fmt::{self, Write};
 
+const STDIN: usize = 0;
 const STDOUT: usize = 1;
 
 struct Stdout;
@@ -15,6 +16,46 @@ pub fn print(args: fmt::Arguments) {
     Stdout.write_fmt(args).unwrap();
 }
 
+// 阻塞直到读到一个字符
+pub fn getchar() -> u8 {
+    let mut c = [0u8; 1];
+    sys_read(STDIN, &mut c);
+    c[0]
+}
+
+const LF: u8 = 0x0a;
+const CR: u8 = 0x0d;
+const BS: u8 = 0x08;
+const DL: u8 = 0x7f;
+
+// 读取一行到 buf 中（不包括换行符），同时回显输入，返回读到的字节数
+// 超出 buf 长度的输入会被丢弃
+pub fn read_line(buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        match getchar() {
+            LF | CR => {
+                sys_write(STDOUT, &[LF]);
+                return len;
+            }
+            BS | DL => {
+                if len > 0 {
+                    len -= 1;
+                    // 退格，用空格覆盖，再退格
+                    sys_write(STDOUT, &[BS, b' ', BS]);
+                }
+            }
+            c => {
+                if len < buf.len() {
+                    buf[len] = c;
+                    len += 1;
+                    sys_write(STDOUT, &[c]);
+                }
+            }
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! print {
     ($fmt: literal $(, $($arg: tt)+)?) => {
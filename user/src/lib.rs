@@ -7,7 +7,7 @@ pub mod console;
 mod lang_items;
 pub mod syscall;
 
-use crate::syscall::sys_exit;
+use crate::syscall::{sys_exit, sys_waitpid, sys_yield};
 
 #[no_mangle]
 #[link_section = ".text.entry"]
@@ -22,3 +22,20 @@ pub extern "C" fn _start() -> ! {
 fn main() -> i32 {
     panic!("Can not find main!");
 }
+
+// 等待任意一个子进程退出，子进程仍在运行时让出 CPU
+pub fn wait(exit_code: &mut i32) -> isize {
+    waitpid(-1, exit_code)
+}
+
+pub fn waitpid(pid: isize, exit_code: &mut i32) -> isize {
+    loop {
+        match sys_waitpid(pid, exit_code as *mut _) {
+            -2 => {
+                sys_yield();
+            }
+            // -1 或者真正的 pid
+            exit_pid => return exit_pid,
+        }
+    }
+}
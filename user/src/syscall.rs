@@ -14,6 +14,11 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+const SYSCALL_READ: usize = 63;
+pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
+    syscall(SYSCALL_READ, [fd, buffer.as_mut_ptr() as usize, buffer.len()])
+}
+
 const SYSCALL_WRITE: usize = 64;
 pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
@@ -24,12 +29,78 @@ pub fn sys_exit(xstate: i32) -> isize {
     syscall(SYSCALL_EXIT, [xstate as usize, 0, 0])
 }
 
+const SYSCALL_SLEEP: usize = 101;
+// 阻塞当前任务至少 ms 毫秒
+pub fn sys_sleep(ms: usize) -> isize {
+    syscall(SYSCALL_SLEEP, [ms, 0, 0])
+}
+
 const SYSCALL_YIELD: usize = 124;
 pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
 
+const SYSCALL_SET_PRIORITY: usize = 140;
+// 优先级需要不小于 2，成功时返回设置的优先级，否则返回 -1
+pub fn sys_set_priority(priority: isize) -> isize {
+    syscall(SYSCALL_SET_PRIORITY, [priority as usize, 0, 0])
+}
+
 const SYSCALL_GET_TIME: usize = 169;
 pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0])
 }
+
+const SYSCALL_SBRK: usize = 214;
+// 将堆的末尾移动 size 字节，返回原来的 program break，失败时返回 -1
+pub fn sys_sbrk(size: i32) -> isize {
+    syscall(SYSCALL_SBRK, [size as usize, 0, 0])
+}
+
+const SYSCALL_MUNMAP: usize = 215;
+// 解除 [start, start + len) 的映射，start 需要页对齐，范围内的每一页都必须已经映射
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [start, len, 0])
+}
+
+const SYSCALL_FORK: usize = 220;
+pub fn sys_fork() -> isize {
+    syscall(SYSCALL_FORK, [0, 0, 0])
+}
+
+const SYSCALL_EXEC: usize = 221;
+// path 需要以 \0 结尾，例如 "hello_world\0"
+pub fn sys_exec(path: &str) -> isize {
+    syscall(SYSCALL_EXEC, [path.as_ptr() as usize, 0, 0])
+}
+
+const SYSCALL_MMAP: usize = 222;
+// 在 start 处映射 len 字节的匿名内存，prot 的第 0、1、2 位分别表示可读、可写、可执行
+// 成功返回 0，start 没有页对齐、prot 不合法或者与已有的映射重叠时返回 -1
+pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MMAP, [start, len, prot])
+}
+
+const SYSCALL_WAITPID: usize = 260;
+pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
+}
+
+const SYSCALL_SCHED_SETATTR: usize = 274;
+// 成为周期为 period 毫秒、每个周期最多运行 budget 毫秒的实时任务，参数不合法时返回 -1
+pub fn sys_sched_setattr(period: usize, budget: usize) -> isize {
+    syscall(SYSCALL_SCHED_SETATTR, [period, budget, 0])
+}
+
+const SYSCALL_SPAWN: usize = 400;
+// path 需要以 \0 结尾，成功时返回子进程的 pid
+pub fn sys_spawn(path: &str) -> isize {
+    syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
+}
+
+pub use abi::{TaskInfo, TaskStatus, MAX_SYSCALL_NUM};
+
+const SYSCALL_TASK_INFO: usize = 410;
+pub fn sys_task_info(info: &mut TaskInfo) -> isize {
+    syscall(SYSCALL_TASK_INFO, [info as *mut _ as usize, 0, 0])
+}